@@ -0,0 +1,215 @@
+//! # espocrm-rs-derive
+//!
+//! Derive macros for [espocrm-rs](https://docs.rs/espocrm-rs). You should not depend on this crate directly,
+//! instead enable the `derive` feature of `espocrm-rs`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, LitStr};
+
+/// Derive `espocrm_rs::Entity` for a struct with named fields.
+///
+/// The entity type defaults to the name of the struct, and can be overridden with `#[espo(entity = "...")]`.
+/// The fields are the attributes serde deserializes the struct from, so they follow `#[serde(rename_all = "...")]` and `#[serde(rename = "...")]`.
+/// Use `#[serde(rename_all = "camelCase")]` to map snake_case fields to EspoCRM's camelCase attributes.
+/// A field can be excluded with `#[espo(skip)]`, `#[serde(skip)]` or `#[serde(skip_deserializing)]`.
+#[proc_macro_derive(Entity, attributes(espo))]
+pub fn derive_entity(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand_entity(input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_entity(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let mut entity_type = input.ident.to_string();
+    let mut rename_all = None;
+    for attr in &input.attrs {
+        if attr.path().is_ident("espo") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("entity") {
+                    entity_type = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("Unsupported espo attribute, expected `entity`"))
+                }
+            })?;
+        } else if attr.path().is_ident("serde") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename_all") {
+                    let rule = parse_serde_name(&meta)?;
+                    rename_all = Some(RenameRule::from_lit(&rule)?);
+                    Ok(())
+                } else {
+                    skip_serde_meta(&meta)
+                }
+            })?;
+        }
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(syn::Error::new_spanned(&input.ident, "Entity can only be derived for structs with named fields")),
+        },
+        _ => return Err(syn::Error::new_spanned(&input.ident, "Entity can only be derived for structs")),
+    };
+
+    let mut field_names = Vec::new();
+    for field in fields {
+        let ident = field.ident.as_ref().expect("Named fields have an ident");
+        let mut serde_name = None;
+        let mut skip = false;
+
+        for attr in &field.attrs {
+            if attr.path().is_ident("espo") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("skip") {
+                        skip = true;
+                        Ok(())
+                    } else if meta.path.is_ident("rename") {
+                        Err(meta.error("Use `#[serde(rename = \"...\")]`, so the field is deserialized from the same attribute"))
+                    } else {
+                        Err(meta.error("Unsupported espo attribute, expected `skip`"))
+                    }
+                })?;
+            } else if attr.path().is_ident("serde") {
+                // Only look at the serde attributes we care about, serde validates the rest
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        serde_name = Some(parse_serde_name(&meta)?.value());
+                        Ok(())
+                    } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                        // Fields which are only skipped when serializing are still read, so they are selected
+                        skip = true;
+                        Ok(())
+                    } else {
+                        skip_serde_meta(&meta)
+                    }
+                })?;
+            }
+        }
+
+        if !skip {
+            let ident = ident.to_string();
+            let ident = ident.strip_prefix("r#").unwrap_or(&ident);
+            let default_name = || match rename_all {
+                Some(rule) => rule.apply(ident),
+                None => ident.to_string(),
+            };
+            field_names.push(serde_name.unwrap_or_else(default_name));
+        }
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::espocrm_rs::Entity for #ident #ty_generics #where_clause {
+            const ENTITY_TYPE: &'static str = #entity_type;
+            const FIELDS: &'static [&'static str] = &[#(#field_names),*];
+        }
+    })
+}
+
+/// Convert a snake_case identifier to camelCase
+fn to_camel_case(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut uppercase_next = false;
+
+    for c in input.trim_start_matches('_').chars() {
+        if c == '_' {
+            uppercase_next = true;
+        } else if uppercase_next {
+            output.extend(c.to_uppercase());
+            uppercase_next = false;
+        } else {
+            output.push(c);
+        }
+    }
+
+    output
+}
+
+/// Parse the value of a serde `rename` or `rename_all`, which is either `= "..."` or `(serialize = "...", deserialize = "...")`.
+/// The name records are deserialized from is used, as that is the attribute EspoCRM returns
+fn parse_serde_name(meta: &ParseNestedMeta) -> syn::Result<LitStr> {
+    if meta.input.peek(syn::Token![=]) {
+        return meta.value()?.parse();
+    }
+
+    let mut serialize = None;
+    let mut deserialize = None;
+    meta.parse_nested_meta(|meta| {
+        if meta.path.is_ident("serialize") {
+            serialize = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else if meta.path.is_ident("deserialize") {
+            deserialize = Some(meta.value()?.parse::<LitStr>()?);
+            Ok(())
+        } else {
+            Err(meta.error("Expected `serialize` or `deserialize`"))
+        }
+    })?;
+
+    deserialize.or(serialize).ok_or_else(|| meta.error("Expected `serialize` or `deserialize`"))
+}
+
+/// Consume a serde attribute the derive does not use, such as `default` or `with = "..."`
+fn skip_serde_meta(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(syn::Token![=]) {
+        meta.value()?.parse::<syn::Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in meta.input);
+        content.parse::<proc_macro2::TokenStream>()?;
+    }
+
+    Ok(())
+}
+
+/// The case conversions of serde's `rename_all`, applied to snake_case field names
+#[derive(Clone, Copy)]
+enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn from_lit(lit: &LitStr) -> syn::Result<Self> {
+        Ok(match lit.value().as_str() {
+            "lowercase" => Self::Lower,
+            "UPPERCASE" => Self::Upper,
+            "PascalCase" => Self::Pascal,
+            "camelCase" => Self::Camel,
+            "snake_case" => Self::Snake,
+            "SCREAMING_SNAKE_CASE" => Self::ScreamingSnake,
+            "kebab-case" => Self::Kebab,
+            "SCREAMING-KEBAB-CASE" => Self::ScreamingKebab,
+            _ => return Err(syn::Error::new_spanned(lit, "Unknown rename_all rule")),
+        })
+    }
+
+    fn apply(self, field: &str) -> String {
+        match self {
+            Self::Lower | Self::Snake => field.to_string(),
+            Self::Upper | Self::ScreamingSnake => field.to_ascii_uppercase(),
+            Self::Pascal => {
+                let camel = to_camel_case(field);
+                let mut chars = camel.chars();
+                chars.next().map(|x| x.to_uppercase().chain(chars).collect()).unwrap_or_default()
+            }
+            Self::Camel => to_camel_case(field),
+            Self::Kebab => field.replace('_', "-"),
+            Self::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use crate::espocrm_error::EspoError;
+use crate::hmac_signer::{HmacSigner, HMAC_HEADER};
+use crate::middleware::{AfterReceive, EspoRequest, Middleware, ResponseHead};
+use crate::secret::Secret;
+use crate::session::{Session, TwoFactorCode};
+use crate::trace_if;
+use reqwest::StatusCode;
+use std::error::Error;
+use std::fmt::Debug;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use zeroize::Zeroizing;
+
+/// Provides the authentication headers for requests to EspoCRM, for strategies not covered by [Auth].
+///
+/// The provider is called for every request, including every retry, so it can rotate credentials.
+/// The headers it returns are marked as sensitive, so reqwest won't include them in its `Debug` output.
+/// ```rust
+/// use espocrm_rs::{Auth, AuthProvider, EspoApiClient};
+/// use std::error::Error;
+///
+/// /// Reads the API key from a file managed by a secrets manager
+/// #[derive(Debug)]
+/// struct SecretsFile {
+///     path: String,
+/// }
+///
+/// impl AuthProvider for SecretsFile {
+///     fn headers(&self, _method: &str, _action: &str) -> Result<Vec<(String, String)>, Box<dyn Error + Send + Sync>> {
+///         let api_key = std::fs::read_to_string(&self.path)?;
+///         Ok(vec![
+///             ("X-Api-Key".to_string(), api_key.trim().to_string()),
+///             // Required by the reverse proxy in front of EspoCRM
+///             ("X-Proxy-Token".to_string(), "proxy token".to_string()),
+///         ])
+///     }
+/// }
+///
+/// let client = EspoApiClient::new("https://espocrm.example.com")
+///     .set_auth(Auth::custom(SecretsFile { path: "/run/secrets/espocrm".to_string() }))
+///     .build();
+/// ```
+pub trait AuthProvider: Debug + Send + Sync {
+    /// The headers to add to a request. `method` is the HTTP method, e.g. `GET`,
+    /// and `action` is the part of the URL after `/api/v1/`, e.g. `Contact/1`.
+    ///
+    /// # Errors
+    ///
+    /// If the credentials can't be provided. The request is not sent, and the error is returned as [EspoError::Auth](crate::EspoError::Auth)
+    fn headers(&self, method: &str, action: &str) -> Result<Vec<(String, String)>, Box<dyn Error + Send + Sync>>;
+}
+
+/// The authentication strategy used by the client, set with [set_auth](crate::EspoApiClient::set_auth).
+/// Exactly one strategy is active at a time.
+/// ```rust
+/// use espocrm_rs::{Auth, EspoApiClient};
+///
+/// let client = EspoApiClient::new("https://espocrm.example.com")
+///     .set_auth(Auth::hmac("Your API Key here", "Your Secret Key here"))
+///     .build();
+/// ```
+///
+/// Credentials are zeroed in memory when dropped, and are redacted from the `Debug` output.
+///
+/// The client adds the credentials to requests as a [Middleware].
+#[derive(Clone, Debug)]
+pub struct Auth(pub(crate) AuthKind);
+
+#[derive(Clone, Debug)]
+pub(crate) enum AuthKind {
+    None,
+    Basic { username: String, password: Secret },
+    ApiKey(Secret),
+    Hmac(HmacSigner),
+    Header { name: String, value: Secret },
+    Session(Arc<Session>),
+    Custom(Arc<dyn AuthProvider>),
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+impl Auth {
+    /// Don't authenticate
+    pub fn none() -> Self {
+        Self(AuthKind::None)
+    }
+
+    /// Authenticate with a username and password.
+    /// It is not recommended that you use this. Instead you should use API Key authorization or HMAC authorization
+    pub fn basic<U: AsRef<str>, P: AsRef<str>>(username: U, password: P) -> Self {
+        Self(AuthKind::Basic {
+            username: username.as_ref().to_string(),
+            password: Secret::new(password.as_ref()),
+        })
+    }
+
+    /// Authenticate with the API key of an API user, sent in the `X-Api-Key` header
+    pub fn api_key<S: AsRef<str>>(api_key: S) -> Self {
+        Self(AuthKind::ApiKey(Secret::new(api_key.as_ref())))
+    }
+
+    /// Authenticate with the API key and secret key of an API user. Every request is signed, so the secret key is never sent.
+    /// See [HmacSigner] for what the signature covers
+    pub fn hmac<K: AsRef<str>, S: AsRef<str>>(api_key: K, secret_key: S) -> Self {
+        Self(AuthKind::Hmac(HmacSigner::new(api_key, secret_key)))
+    }
+
+    /// Log in with a username and password once, and authenticate the following requests with the token issued by EspoCRM.
+    /// The client logs in before its first request, and logs in again if EspoCRM rejects the token.
+    ///
+    /// Unlike [Self::basic], EspoCRM only verifies the password when logging in, so it won't lock out the user after many requests.
+    /// Clones of the client share the token. The logged in user is available from [session_user](crate::EspoApiClient::session_user)
+    pub fn session<U: AsRef<str>, P: AsRef<str>>(username: U, password: P) -> Self {
+        Self(AuthKind::Session(Arc::new(Session::new(username.as_ref(), password.as_ref(), None))))
+    }
+
+    /// Like [Self::session], for users with two-factor authentication enabled.
+    /// When EspoCRM asks for the second step of the login, `code` is sent along with the password
+    pub fn session_2fa<U: AsRef<str>, P: AsRef<str>>(username: U, password: P, code: TwoFactorCode) -> Self {
+        Self(AuthKind::Session(Arc::new(Session::new(username.as_ref(), password.as_ref(), Some(code)))))
+    }
+
+    /// Authenticate by sending `value` in the header `name`, e.g. for a reverse proxy which handles authentication
+    pub fn header<N: AsRef<str>, V: AsRef<str>>(name: N, value: V) -> Self {
+        Self(AuthKind::Header {
+            name: name.as_ref().to_string(),
+            value: Secret::new(value.as_ref()),
+        })
+    }
+
+    /// Authenticate using a custom [AuthProvider]
+    pub fn custom<P: AuthProvider + 'static>(provider: P) -> Self {
+        Self(AuthKind::Custom(Arc::new(provider)))
+    }
+
+    pub(crate) fn as_session(&self) -> Option<&Session> {
+        match &self.0 {
+            AuthKind::Session(session) => Some(session),
+            _ => None,
+        }
+    }
+}
+
+impl Middleware for Auth {
+    fn before_send(&self, request: &mut EspoRequest) -> Result<(), EspoError> {
+        match &self.0 {
+            AuthKind::None => Ok(()),
+            AuthKind::Basic { username, password } => {
+                trace_if!("Using basic authentication");
+                let credentials = Zeroizing::new(format!("{}:{}", username, password.expose()));
+                let header = Zeroizing::new(format!("Basic {}", base64::encode(credentials.as_bytes())));
+                request.set_sensitive_header("Authorization", &header)
+            }
+            AuthKind::Hmac(signer) => {
+                trace_if!("Using HMAC authentication.");
+                let header = signer.sign(request.method.as_str(), &request.action).map_err(|x| EspoError::Auth(Box::new(x)))?;
+                request.set_sensitive_header(HMAC_HEADER, &Zeroizing::new(header))
+            }
+            AuthKind::ApiKey(api_key) => {
+                trace_if!("Authenticating with an API key");
+                request.set_sensitive_header("X-Api-Key", api_key.expose())
+            }
+            AuthKind::Header { name, value } => {
+                trace_if!("Authenticating with the {name} header");
+                request.set_sensitive_header(name, value.expose())
+            }
+            // The login request already carries the password
+            AuthKind::Session(_) if request.login => Ok(()),
+            AuthKind::Session(session) => {
+                trace_if!("Authenticating with a session token");
+                session.authorize_with_token(request)
+            }
+            AuthKind::Custom(provider) => {
+                trace_if!("Authenticating with a custom provider");
+                let headers = provider.headers(request.method.as_str(), &request.action).map_err(EspoError::Auth)?;
+                headers.iter().try_for_each(|(name, value)| request.set_sensitive_header(name, value))
+            }
+        }
+    }
+
+    /// Log in again if EspoCRM rejects the token of a session, once per request.
+    /// A rejected login is not retried, as the password or two-factor code is wrong
+    fn after_receive(&self, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> AfterReceive {
+        match (&self.0, response) {
+            (AuthKind::Session(session), Ok(response))
+                if response.status == StatusCode::UNAUTHORIZED && !request.login && !request.token_refreshed.swap(true, Ordering::Relaxed) =>
+            {
+                trace_if!("EspoCRM rejected the session token, logging in again");
+                session.invalidate(request);
+                AfterReceive::Retry(std::time::Duration::ZERO)
+            }
+            _ => AfterReceive::Continue,
+        }
+    }
+}
+
+/// Credentials set with the deprecated setters of the client, which are combined into an [Auth]
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Credentials {
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<Secret>,
+    pub(crate) api_key: Option<Secret>,
+    pub(crate) secret_key: Option<Secret>,
+}
+
+impl Credentials {
+    /// Pick a strategy based on which credentials are set: basic, then HMAC, then API key authentication
+    pub(crate) fn to_auth(&self) -> Auth {
+        match self {
+            Self { username: Some(username), password: Some(password), .. } => Auth(AuthKind::Basic {
+                username: username.clone(),
+                password: password.clone(),
+            }),
+            Self { api_key: Some(api_key), secret_key: Some(secret_key), .. } => Auth::hmac(api_key.expose(), secret_key.expose()),
+            Self { api_key: Some(api_key), .. } => Auth(AuthKind::ApiKey(api_key.clone())),
+            _ => Auth::none(),
+        }
+    }
+}
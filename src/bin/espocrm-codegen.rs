@@ -0,0 +1,52 @@
+//! Generate Rust entity structs from a snapshot of EspoCRM's metadata.
+//!
+//! Usage: `espocrm-codegen <metadata.json> [--output <file>] [--entity <EntityType>]...`
+//!
+//! The metadata can be saved with e.g. `curl -H "X-Api-Key: ..." https://espocrm.example.com/api/v1/Metadata > metadata.json`.
+
+use espocrm_rs::{generate_entities_from_json, CodegenOptions};
+use std::process::exit;
+
+const USAGE: &str = "Usage: espocrm-codegen <metadata.json> [--output <file>] [--entity <EntityType>]...";
+
+fn main() {
+    let mut input = None;
+    let mut output = None;
+    let mut entities = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = Some(args.next().unwrap_or_else(|| fail("Missing value for --output"))),
+            "-e" | "--entity" => entities.push(args.next().unwrap_or_else(|| fail("Missing value for --entity"))),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return;
+            }
+            _ if input.is_none() => input = Some(arg),
+            _ => fail(&format!("Unexpected argument: {arg}")),
+        }
+    }
+
+    let input = input.unwrap_or_else(|| fail("Missing metadata file"));
+    let metadata = std::fs::read_to_string(&input).unwrap_or_else(|e| fail(&format!("Failed to read {input}: {e}")));
+
+    let mut options = CodegenOptions::new();
+    if !entities.is_empty() {
+        options.set_entities(&entities);
+    }
+
+    let code = generate_entities_from_json(&metadata, &options)
+        .unwrap_or_else(|e| fail(&format!("Failed to parse {input}: {e}")));
+
+    match output {
+        Some(output) => std::fs::write(&output, code).unwrap_or_else(|e| fail(&format!("Failed to write {output}: {e}"))),
+        None => print!("{code}"),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    eprintln!("{USAGE}");
+    exit(1);
+}
@@ -0,0 +1,642 @@
+//! A blocking client for the EspoCRM API, available with the `blocking` feature.
+//!
+//! [EspoBlockingClient] has the same methods as [EspoApiClient], but they block the current thread instead of returning a future.
+//! Authentication, serialization of the query, retries and limits are shared with the async client.
+//! ```rust,no_run
+//! use espocrm_rs::blocking::EspoBlockingClient;
+//! use espocrm_rs::{Auth, Method, NoGeneric, Params};
+//!
+//! let client = EspoBlockingClient::new("https://espocrm.example.com")
+//!     .set_auth(Auth::hmac("Your API Key here", "Your Secret Key here"))
+//!     .build();
+//!
+//! let params = Params::new().set_max_size(10).build();
+//! let response = client.request::<NoGeneric, &str>(Method::Get, "Contact", Some(params), None).unwrap();
+//! ```
+//!
+//! Like [reqwest::blocking], the client must not be created or used from within an async runtime.
+
+use crate::auth::Auth;
+use crate::call::{self, Call};
+use crate::espocrm_api_client::{EspoApiClient, Method};
+use crate::espocrm_error::EspoError;
+use crate::espocrm_metadata::Metadata;
+use crate::espocrm_types::{Entity, ListResponse, PaginationOptions, Params};
+use crate::middleware::{EspoRequest, Middleware, ResponseHead};
+use crate::pagination::PageState;
+use crate::retry::RetryPolicy;
+use crate::secret::Secret;
+use crate::session::{Session, SessionUser};
+use crate::trace_if;
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt::Debug;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::Duration;
+
+/// Blocking client for the EspoCRM API. See [EspoApiClient] for the documentation of its methods.
+///
+/// Requests are always sent with a [reqwest::blocking::Client]. As [Transport](crate::Transport) is async, there is no `set_transport`.
+///
+/// Cloning the client is cheap, and clones share their connections and limits.
+#[derive(Clone, Debug)]
+pub struct EspoBlockingClient {
+    /// Holds the configuration shared with the async client
+    inner: EspoApiClient,
+    /// Set with [Self::set_http_client]
+    http_client: Option<Client>,
+    /// Built from the connection options when the first request is sent, unless `http_client` is set
+    built_http_client: Arc<OnceLock<Client>>,
+    /// Used instead of the async client's semaphore, which can't be waited on without an executor
+    in_flight: Option<Arc<InFlight>>,
+}
+
+/// Limits the number of requests in flight, blocking the current thread while waiting for a request to finish
+#[derive(Debug)]
+pub(crate) struct InFlight {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Returns its slot to the [InFlight] limit when dropped
+pub(crate) struct InFlightPermit<'a>(&'a InFlight);
+
+impl InFlight {
+    pub(crate) fn new(max_in_flight: usize) -> Self {
+        Self {
+            available: Mutex::new(max_in_flight),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until fewer than the maximum number of requests are in flight
+    pub(crate) fn acquire(&self) -> InFlightPermit<'_> {
+        let mut available = self.available.lock().unwrap_or_else(|x| x.into_inner());
+        while *available == 0 {
+            available = self.released.wait(available).unwrap_or_else(|x| x.into_inner());
+        }
+
+        *available -= 1;
+        InFlightPermit(self)
+    }
+}
+
+impl Drop for InFlightPermit<'_> {
+    fn drop(&mut self) {
+        *self.0.available.lock().unwrap_or_else(|x| x.into_inner()) += 1;
+        self.0.released.notify_one();
+    }
+}
+
+impl EspoBlockingClient {
+    /// Create an instance of EspoBlockingClient.
+    ///
+    /// Its methods block the current thread. Like [reqwest::blocking::Client], they panic when called from within an async runtime,
+    /// use [EspoApiClient] there instead
+    pub fn new(url: &str) -> EspoBlockingClient {
+        EspoBlockingClient {
+            inner: EspoApiClient::new(url),
+            http_client: None,
+            built_http_client: Arc::default(),
+            in_flight: None,
+        }
+    }
+
+    pub fn build(&self) -> Self {
+        self.clone()
+    }
+
+    /// See [EspoApiClient::set_url]
+    pub fn set_url<S: AsRef<str>>(&mut self, url: S) -> &mut EspoBlockingClient {
+        self.inner.set_url(url);
+        self
+    }
+
+    /// See [EspoApiClient::set_auth]
+    pub fn set_auth(&mut self, auth: Auth) -> &mut EspoBlockingClient {
+        self.inner.set_auth(auth);
+        self
+    }
+
+    /// See [EspoApiClient::add_middleware]
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) -> &mut EspoBlockingClient {
+        self.inner.add_middleware(middleware);
+        self
+    }
+
+    /// See [EspoApiClient::set_username]
+    #[deprecated(note = "use `set_auth(Auth::basic(username, password))` instead")]
+    #[allow(deprecated)]
+    pub fn set_username<S: AsRef<str>>(&mut self, username: S) -> &mut EspoBlockingClient {
+        self.inner.set_username(username);
+        self
+    }
+
+    /// See [EspoApiClient::set_password]
+    #[deprecated(note = "use `set_auth(Auth::basic(username, password))` instead")]
+    #[allow(deprecated)]
+    pub fn set_password<S: AsRef<str>>(&mut self, password: S) -> &mut EspoBlockingClient {
+        self.inner.set_password(password);
+        self
+    }
+
+    /// See [EspoApiClient::set_api_key]
+    #[deprecated(note = "use `set_auth(Auth::api_key(api_key))` or `set_auth(Auth::hmac(api_key, secret_key))` instead")]
+    #[allow(deprecated)]
+    pub fn set_api_key<S: AsRef<str>>(&mut self, api_key: S) -> &mut EspoBlockingClient {
+        self.inner.set_api_key(api_key);
+        self
+    }
+
+    /// See [EspoApiClient::set_secret_key]
+    #[deprecated(note = "use `set_auth(Auth::hmac(api_key, secret_key))` instead")]
+    #[allow(deprecated)]
+    pub fn set_secret_key<S: AsRef<str>>(&mut self, secret_key: S) -> &mut EspoBlockingClient {
+        self.inner.set_secret_key(secret_key);
+        self
+    }
+
+    /// Set the [reqwest::blocking::Client] used to send requests.
+    /// Unlike the async client, the blocking client always sends requests with reqwest, as [Transport](crate::Transport) is async.
+    ///
+    /// The connect and read timeouts and TLS options of this crate don't apply to this client, configure them on the client instead
+    pub fn set_http_client(&mut self, http_client: Client) -> &mut EspoBlockingClient {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// See [EspoApiClient::set_retry_policy]
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut EspoBlockingClient {
+        self.inner.set_retry_policy(retry_policy);
+        self
+    }
+
+    /// See [EspoApiClient::set_rate_limit]
+    pub fn set_rate_limit(&mut self, requests_per_second: f64, burst: u32) -> &mut EspoBlockingClient {
+        self.inner.set_rate_limit(requests_per_second, burst);
+        self
+    }
+
+    /// See [EspoApiClient::set_max_in_flight]
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) -> &mut EspoBlockingClient {
+        self.in_flight = (max_in_flight > 0).then(|| Arc::new(InFlight::new(max_in_flight)));
+        self
+    }
+
+    /// See [EspoApiClient::set_connect_timeout]
+    pub fn set_connect_timeout(&mut self, timeout: Duration) -> &mut EspoBlockingClient {
+        self.inner.set_connect_timeout(timeout);
+        self.built_http_client = Arc::default();
+        self
+    }
+
+    /// See [EspoApiClient::set_read_timeout]
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> &mut EspoBlockingClient {
+        self.inner.set_read_timeout(timeout);
+        self.built_http_client = Arc::default();
+        self
+    }
+
+    /// See [EspoApiClient::add_root_certificate]
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub fn add_root_certificate(&mut self, certificate: reqwest::Certificate) -> &mut EspoBlockingClient {
+        self.inner.add_root_certificate(certificate);
+        self.built_http_client = Arc::default();
+        self
+    }
+
+    /// See [EspoApiClient::set_accept_invalid_certs]
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub fn set_accept_invalid_certs(&mut self, accept_invalid_certs: bool) -> &mut EspoBlockingClient {
+        self.inner.set_accept_invalid_certs(accept_invalid_certs);
+        self.built_http_client = Arc::default();
+        self
+    }
+
+    /// See [EspoApiClient::set_timeout]
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut EspoBlockingClient {
+        self.inner.set_timeout(timeout);
+        self
+    }
+
+    /// See [EspoApiClient::with_timeout]
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        let mut client = self.clone();
+        client.inner.set_timeout(timeout);
+        client
+    }
+
+    /// The client set with [Self::set_http_client], or the one built from the connection options.
+    /// The built client is shared by all clones made before one of the options is changed
+    fn http_client(&self) -> Result<&Client, EspoError> {
+        if let Some(http_client) = self.http_client.as_ref().or_else(|| self.built_http_client.get()) {
+            return Ok(http_client);
+        }
+
+        // reqwest's blocking client has a timeout of 30 seconds by default, the async client has none
+        let http_client = reqwest::blocking::ClientBuilder::from(self.inner.http_client_builder())
+            .timeout(None)
+            .build()
+            .map_err(EspoError::Transport)?;
+        Ok(self.built_http_client.get_or_init(|| http_client))
+    }
+
+    /// See [EspoApiClient::create_allow_duplicates]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub fn create_allow_duplicates<T, S>(&self, action: S, data: T) -> Result<Response, EspoError>
+    where
+        T: Serialize + Clone + Debug,
+        S: AsRef<str>,
+    {
+        self.execute(Call::create_allow_duplicates(action.as_ref(), &data)?)
+    }
+
+    /// See [EspoApiClient::create]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code.
+    /// Duplicates are reported as [EspoError::Duplicate]
+    pub fn create<T, S>(&self, action: S, data: T) -> Result<Response, EspoError>
+    where
+        T: Serialize + Clone + Debug,
+        S: AsRef<str>,
+    {
+        self.execute(Call::create(action.as_ref(), &data)?)
+    }
+
+    /// See [EspoApiClient::request]
+    ///
+    /// # Errors
+    ///
+    /// If `data_get` can't be serialized, the request fails, or EspoCRM returns a non-success status code
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, data_get, data_post)))]
+    pub fn request<T, S>(
+        &self,
+        method: Method,
+        action: S,
+        data_get: Option<Params>,
+        data_post: Option<T>,
+    ) -> Result<Response, EspoError>
+    where
+        T: Serialize + Clone + Debug,
+        S: AsRef<str> + Debug,
+    {
+        self.execute(Call::request(method, action.as_ref(), data_get, data_post.as_ref())?)
+    }
+
+    /// See [EspoApiClient::execute]
+    fn execute(&self, call: Call) -> Result<Response, EspoError> {
+        // The caller reads the body, so the response no longer counts as in flight
+        let (response, _permit) = self.execute_in_flight(call)?;
+        Ok(response)
+    }
+
+    /// See [EspoApiClient::execute_json]
+    fn execute_json<T: DeserializeOwned>(&self, call: Call) -> Result<T, EspoError> {
+        let (response, _permit) = self.execute_in_flight(call)?;
+        call::deserialize_body(&response.bytes()?)
+    }
+
+    /// See [EspoApiClient::execute_in_flight]
+    fn execute_in_flight(&self, call: Call) -> Result<(Response, Option<InFlightPermit<'_>>), EspoError> {
+        let (response, permit) = self.send(self.inner.new_request(call)?)?;
+        Ok((EspoError::check_blocking_response(response)?, permit))
+    }
+
+    /// See [EspoApiClient::send]
+    fn send(&self, base_request: EspoRequest) -> Result<(Response, Option<InFlightPermit<'_>>), EspoError> {
+        let mut attempt = 1;
+
+        loop {
+            if let Some(session) = self.inner.auth.as_session().filter(|_| !base_request.login) {
+                self.ensure_logged_in(session)?;
+            }
+
+            let permit = self.acquire_limits();
+            let request = self.inner.prepare_attempt(&base_request, attempt)?;
+            let result = self.to_reqwest(&request).and_then(|x| x.send().map_err(EspoError::from));
+
+            let head = result.as_ref().map(|x| ResponseHead { status: x.status(), headers: x.headers() });
+            match self.inner.retry_delay(&request, head) {
+                Some(delay) => {
+                    drop(permit);
+                    std::thread::sleep(delay);
+                }
+                None => return Ok((result?, permit)),
+            }
+
+            attempt += 1;
+        }
+    }
+
+    /// See [EspoApiClient::acquire_limits]
+    fn acquire_limits(&self) -> Option<InFlightPermit<'_>> {
+        let permit = self.in_flight.as_ref().map(|x| x.acquire());
+
+        if let Some(rate_limiter) = &self.inner.rate_limiter {
+            rate_limiter.acquire_blocking();
+        }
+
+        permit
+    }
+
+    fn to_reqwest(&self, request: &EspoRequest) -> Result<RequestBuilder, EspoError> {
+        let mut request_builder = self.http_client()?
+            .request(request.method.clone(), &request.url)
+            .headers(request.headers.clone());
+
+        if let Some(body) = &request.body {
+            request_builder = request_builder.body(body.clone());
+        }
+
+        if let Some(timeout) = request.timeout {
+            request_builder = request_builder.timeout(timeout);
+        }
+
+        Ok(request_builder)
+    }
+
+    /// Log in with `session` if there is no token
+    fn ensure_logged_in(&self, session: &Session) -> Result<(), EspoError> {
+        if session.token().is_some() {
+            return Ok(());
+        }
+
+        let _guard = session.login_lock.blocking_lock();
+        // Another request may have logged in while this one was waiting
+        if session.token().is_some() {
+            return Ok(());
+        }
+
+        self.send_login(session).map(drop)
+    }
+
+    fn send_login(&self, session: &Session) -> Result<SessionUser, EspoError> {
+        trace_if!("Logging in to EspoCRM");
+        let mut response = self.send_login_request(session, None)?;
+
+        if let Some(second_factor) = session.second_step(response.status(), response.headers()) {
+            response = self.send_login_request(session, Some(&second_factor.code()?))?;
+        }
+
+        let response = EspoError::check_blocking_response(response)?;
+
+        session.store(&response.bytes()?)
+    }
+
+    /// See [EspoApiClient::send_login_request]
+    fn send_login_request(&self, session: &Session, code: Option<&Secret>) -> Result<Response, EspoError> {
+        let (response, _permit) = self.send(self.inner.login_request(session, code)?)?;
+        Ok(response)
+    }
+
+    /// See [EspoApiClient::read]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code.
+    /// A record that does not exist is reported as [EspoError::NotFound]
+    pub fn read<S, I>(&self, entity: S, id: I) -> Result<Response, EspoError>
+    where
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::read(entity.as_ref(), id.as_ref()))
+    }
+
+    /// See [EspoApiClient::update]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub fn update<T, S, I>(&self, entity: S, id: I, data: T) -> Result<Response, EspoError>
+    where
+        T: Serialize + Clone + Debug,
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::update(entity.as_ref(), id.as_ref(), &data)?)
+    }
+
+    /// See [EspoApiClient::update_put]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub fn update_put<T, S, I>(&self, entity: S, id: I, data: T) -> Result<Response, EspoError>
+    where
+        T: Serialize + Clone + Debug,
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::update_put(entity.as_ref(), id.as_ref(), &data)?)
+    }
+
+    /// See [EspoApiClient::delete]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub fn delete<S, I>(&self, entity: S, id: I) -> Result<Response, EspoError>
+    where
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::delete(entity.as_ref(), id.as_ref()))
+    }
+
+    /// See [EspoApiClient::create_entity]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the created record could not be deserialized into `T`
+    pub fn create_entity<T>(&self, data: &T) -> Result<T, EspoError>
+    where
+        T: Entity + Serialize + DeserializeOwned + Debug,
+    {
+        self.execute_json(Call::create(T::ENTITY_TYPE, data)?)
+    }
+
+    /// See [EspoApiClient::get]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the record could not be deserialized into `T`
+    pub fn get<T, I>(&self, id: I) -> Result<T, EspoError>
+    where
+        T: Entity + DeserializeOwned,
+        I: AsRef<str>,
+    {
+        self.get_of(T::ENTITY_TYPE, id)
+    }
+
+    /// See [EspoApiClient::get_of]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the record could not be deserialized into `T`
+    pub fn get_of<T, S, I>(&self, entity: S, id: I) -> Result<T, EspoError>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute_json(Call::read(entity.as_ref(), id.as_ref()))
+    }
+
+    /// See [EspoApiClient::update_entity]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the updated record could not be deserialized into `T`
+    pub fn update_entity<T, I>(&self, id: I, data: &T) -> Result<T, EspoError>
+    where
+        T: Entity + Serialize + DeserializeOwned + Debug,
+        I: AsRef<str>,
+    {
+        self.execute_json(Call::update(T::ENTITY_TYPE, id.as_ref(), data)?)
+    }
+
+    /// See [EspoApiClient::delete_entity]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub fn delete_entity<T, I>(&self, id: I) -> Result<(), EspoError>
+    where
+        T: Entity,
+        I: AsRef<str>,
+    {
+        self.delete(T::ENTITY_TYPE, id)?;
+        Ok(())
+    }
+
+    /// See [EspoApiClient::list]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the records could not be deserialized into `T`
+    pub fn list<T>(&self, params: Params) -> Result<ListResponse<T>, EspoError>
+    where
+        T: Entity + DeserializeOwned,
+    {
+        self.list_of(T::ENTITY_TYPE, params)
+    }
+
+    /// See [EspoApiClient::list_of]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the records could not be deserialized into `T`
+    pub fn list_of<T, S>(&self, entity: S, params: Params) -> Result<ListResponse<T>, EspoError>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+    {
+        self.execute_json(Call::list(entity.as_ref(), params)?)
+    }
+
+    /// Fetch all records of entity type `T` matching `params` as an [Iterator], requesting pages lazily as the iterator is consumed.
+    /// The blocking counterpart of [EspoApiClient::list_stream], see its documentation for how `params` and `options` are used.
+    ///
+    /// # Errors
+    ///
+    /// The iterator yields an error if a request fails, EspoCRM returns a non-success status code, or a record could not be deserialized into `T`.
+    /// No more records are returned after an error.
+    pub fn list_iter<T>(&self, params: Params, options: PaginationOptions) -> impl Iterator<Item = Result<T, EspoError>>
+    where
+        T: Entity + DeserializeOwned,
+    {
+        self.list_iter_of(T::ENTITY_TYPE, params, options)
+    }
+
+    /// Like [Self::list_iter], for entity types without an [Entity] implementation. See [EspoApiClient::list_stream_of]
+    ///
+    /// # Errors
+    ///
+    /// The iterator yields an error if a request fails, EspoCRM returns a non-success status code, or a record could not be deserialized into `T`.
+    /// No more records are returned after an error.
+    pub fn list_iter_of<T, S>(&self, entity: S, params: Params, options: PaginationOptions) -> impl Iterator<Item = Result<T, EspoError>>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+    {
+        let client = self.clone();
+        let entity = entity.as_ref().to_string();
+        let mut state = Some(PageState::new(params, options));
+        let mut records = Vec::new().into_iter();
+
+        std::iter::from_fn(move || loop {
+            if let Some(record) = records.next() {
+                return Some(Ok(record));
+            }
+
+            // The state is dropped after an error, ending the iterator
+            let page_state = state.as_mut()?;
+            let params = page_state.next_params()?;
+
+            trace_if!("Fetching page of {entity} from EspoCRM");
+            let page = client
+                .list_of::<serde_json::Value, _>(&entity, params)
+                .and_then(|page| {
+                    page_state.advance(page.total, &page.list)?;
+                    Ok(page.list
+                        .into_iter()
+                        .map(serde_json::from_value)
+                        .collect::<Result<Vec<T>, _>>()?)
+                });
+
+            match page {
+                Ok(page) => records = page.into_iter(),
+                Err(e) => {
+                    state = None;
+                    return Some(Err(e));
+                }
+            }
+        })
+    }
+
+    /// See [EspoApiClient::login]
+    ///
+    /// # Errors
+    ///
+    /// If the client does not use [Auth::session], the request fails, or EspoCRM returns a non-success status code.
+    /// Wrong credentials are reported as [EspoError::Unauthorized]
+    pub fn login(&self) -> Result<SessionUser, EspoError> {
+        let session = self.inner.auth.as_session().ok_or_else(|| EspoError::Auth("the client does not use session authentication".into()))?;
+        let _guard = session.login_lock.blocking_lock();
+        self.send_login(session)
+    }
+
+    /// See [EspoApiClient::session_user]
+    pub fn session_user(&self) -> Option<SessionUser> {
+        self.inner.session_user()
+    }
+
+    /// See [EspoApiClient::metadata]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the metadata could not be deserialized
+    pub fn metadata(&self) -> Result<Metadata, EspoError> {
+        self.execute_json(Call::metadata())
+    }
+
+    /// See [EspoApiClient::metadata_key]
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the metadata could not be deserialized into `T`
+    pub fn metadata_key<T, S>(&self, key: S) -> Result<T, EspoError>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+    {
+        self.execute_json(Call::metadata_key(key.as_ref()))
+    }
+}
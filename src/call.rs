@@ -0,0 +1,106 @@
+use crate::espocrm_api_client::Method;
+use crate::espocrm_error::{EspoError, SerializeError};
+use crate::espocrm_types::Params;
+use crate::session::LOGIN_ACTION;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// A request to EspoCRM as the methods of the async and [blocking](crate::blocking) clients describe it,
+/// before it is turned into an [EspoRequest](crate::EspoRequest) and sent.
+///
+/// Both clients build their requests from these constructors, so they send the same requests for the same method
+#[derive(Clone, Debug)]
+pub(crate) struct Call {
+    pub(crate) method: reqwest::Method,
+    pub(crate) action: String,
+    /// The serialized query string
+    pub(crate) query: Option<String>,
+    /// The body serialized to JSON
+    pub(crate) body: Option<Vec<u8>>,
+    pub(crate) headers: &'static [(&'static str, &'static str)],
+}
+
+impl Call {
+    pub(crate) fn new<S: Into<String>>(method: reqwest::Method, action: S) -> Self {
+        Self {
+            method,
+            action: action.into(),
+            query: None,
+            body: None,
+            headers: &[],
+        }
+    }
+
+    fn with_body<T: Serialize>(mut self, body: &T) -> Result<Self, EspoError> {
+        self.body = Some(serde_json::to_vec(body).map_err(|x| SerializeError::Body(x.to_string()))?);
+        Ok(self)
+    }
+
+    /// See [EspoApiClient::request](crate::EspoApiClient::request).
+    /// `data_get` is only used for GET requests, and `data_post` for all others
+    pub(crate) fn request<T: Serialize>(method: Method, action: &str, data_get: Option<Params>, data_post: Option<&T>) -> Result<Self, EspoError> {
+        let mut call = Self::new(reqwest::Method::from(method), action);
+
+        if call.method == reqwest::Method::GET {
+            call.query = data_get.map(crate::serializer::serialize).transpose()?;
+            return Ok(call);
+        }
+
+        match data_post {
+            Some(data_post) => call.with_body(data_post),
+            None => Ok(call),
+        }
+    }
+
+    pub(crate) fn create<T: Serialize>(action: &str, data: &T) -> Result<Self, EspoError> {
+        Self::new(reqwest::Method::POST, action).with_body(data)
+    }
+
+    pub(crate) fn create_allow_duplicates<T: Serialize>(action: &str, data: &T) -> Result<Self, EspoError> {
+        let mut call = Self::create(action, data)?;
+        call.headers = &[("X-Skip-Duplicate-Check", "true")];
+        Ok(call)
+    }
+
+    pub(crate) fn read(entity: &str, id: &str) -> Self {
+        Self::new(reqwest::Method::GET, format!("{entity}/{id}"))
+    }
+
+    pub(crate) fn update<T: Serialize>(entity: &str, id: &str, data: &T) -> Result<Self, EspoError> {
+        Self::new(reqwest::Method::PATCH, format!("{entity}/{id}")).with_body(data)
+    }
+
+    pub(crate) fn update_put<T: Serialize>(entity: &str, id: &str, data: &T) -> Result<Self, EspoError> {
+        Self::new(reqwest::Method::PUT, format!("{entity}/{id}")).with_body(data)
+    }
+
+    pub(crate) fn delete(entity: &str, id: &str) -> Self {
+        Self::new(reqwest::Method::DELETE, format!("{entity}/{id}"))
+    }
+
+    pub(crate) fn list(entity: &str, params: Params) -> Result<Self, EspoError> {
+        let mut call = Self::new(reqwest::Method::GET, entity);
+        call.query = Some(crate::serializer::serialize(params)?);
+        Ok(call)
+    }
+
+    pub(crate) fn metadata() -> Self {
+        Self::new(reqwest::Method::GET, "Metadata")
+    }
+
+    pub(crate) fn metadata_key(key: &str) -> Self {
+        let mut call = Self::new(reqwest::Method::GET, "Metadata/action/get");
+        call.query = Some(format!("key={}", urlencoding::encode(key)));
+        call
+    }
+
+    /// The request logging in with [Auth::session](crate::Auth::session). The credentials are added by the session
+    pub(crate) fn login() -> Self {
+        Self::new(reqwest::Method::GET, LOGIN_ACTION)
+    }
+}
+
+/// Deserialize the body of a successful response
+pub(crate) fn deserialize_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, EspoError> {
+    Ok(serde_json::from_slice(body)?)
+}
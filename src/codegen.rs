@@ -0,0 +1,277 @@
+use crate::espocrm_metadata::{EntityDefs, FieldDefs, FieldType, Metadata};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// Rust keywords which can't be used as an identifier without the `r#` prefix
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn", "else", "enum",
+    "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop", "macro", "match", "mod", "move",
+    "mut", "override", "priv", "pub", "ref", "return", "static", "struct", "trait", "true", "try", "type",
+    "typeof", "unsafe", "unsized", "use", "virtual", "where", "while", "yield",
+];
+
+/// Keywords which can't be used as a raw identifier either
+const RESERVED: &[&str] = &["crate", "self", "super", "Self"];
+
+/// Options for [generate_entities]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CodegenOptions {
+    /// The entity types to generate. If `None`, all entities in the metadata are generated
+    pub entities: Option<Vec<String>>,
+}
+
+impl CodegenOptions {
+    pub fn new() -> Self {
+        Self { entities: None }
+    }
+
+    /// Only generate the provided entity types
+    pub fn set_entities<S: AsRef<str>>(&mut self, entities: &[S]) -> &mut Self {
+        self.entities = Some(entities.iter().map(|x| x.as_ref().to_string()).collect());
+        self
+    }
+
+    pub fn build(&self) -> Self {
+        self.clone()
+    }
+}
+
+/// Generate Rust source code from a snapshot of EspoCRM's metadata, as returned by `GET /api/v1/Metadata`.
+/// See [generate_entities].
+///
+/// # Errors
+///
+/// If `json` is not valid metadata
+pub fn generate_entities_from_json(json: &str, options: &CodegenOptions) -> Result<String, serde_json::Error> {
+    let metadata: Metadata = serde_json::from_str(json)?;
+    Ok(generate_entities(&metadata, options))
+}
+
+/// Generate Rust source code for the entities described in `metadata`.
+///
+/// For every entity a struct is generated which implements [Entity](crate::Entity), [serde::Serialize] and [serde::Deserialize].
+/// All attributes are optional, as EspoCRM only returns the attributes that were selected.
+/// Enum fields with options get their own enum type, and link fields are expanded into their id and name attributes.
+///
+/// The generated code requires `serde` 1.0.181 or later, with the `derive` feature, as a dependency. It can be written to a file from a build script:
+/// ```no_run
+/// use espocrm_rs::{generate_entities_from_json, CodegenOptions};
+///
+/// let metadata = std::fs::read_to_string("metadata.json").unwrap();
+/// let code = generate_entities_from_json(&metadata, &CodegenOptions::new()).unwrap();
+///
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+/// std::fs::write(format!("{out_dir}/entities.rs"), code).unwrap();
+/// ```
+pub fn generate_entities(metadata: &Metadata, options: &CodegenOptions) -> String {
+    let mut output = String::new();
+    output.push_str("// This file was generated by espocrm-rs from EspoCRM's metadata. Do not edit it by hand.\n");
+
+    for (entity_type, defs) in &metadata.entity_defs {
+        if let Some(entities) = &options.entities {
+            if !entities.contains(entity_type) {
+                continue;
+            }
+        }
+
+        output.push('\n');
+        generate_entity(&mut output, entity_type, defs);
+    }
+
+    output
+}
+
+/// A single attribute of a generated struct
+struct Attribute {
+    name: String,
+    rust_type: String,
+}
+
+fn generate_entity(output: &mut String, entity_type: &str, defs: &EntityDefs) {
+    let struct_name = to_pascal_case(entity_type);
+    let mut enums = String::new();
+    let mut attributes = vec![Attribute {
+        name: "id".to_string(),
+        rust_type: "String".to_string(),
+    }];
+
+    for (field_name, field) in &defs.fields {
+        if field.disabled {
+            continue;
+        }
+
+        attributes.extend(field_attributes(&struct_name, field_name, field, &mut enums));
+    }
+
+    // Fields may also be defined implicitly by another field, e.g. `accountId` by `account`
+    let mut seen = BTreeSet::new();
+    attributes.retain(|x| seen.insert(x.name.clone()));
+
+    output.push_str(&enums);
+
+    writeln!(output, "/// The `{entity_type}` entity").unwrap();
+    output.push_str("#[derive(Clone, Debug, Default, PartialEq, ::serde::Serialize, ::serde::Deserialize)]\n");
+    output.push_str("#[serde(rename_all = \"camelCase\")]\n");
+    writeln!(output, "pub struct {struct_name} {{").unwrap();
+    for attribute in &attributes {
+        let ident = to_identifier(&to_snake_case(&attribute.name));
+        if to_serde_camel_case(ident.trim_start_matches("r#")) != attribute.name {
+            writeln!(output, "    #[serde(rename = {:?})]", attribute.name).unwrap();
+        }
+
+        output.push_str("    #[serde(default, skip_serializing_if = \"Option::is_none\")]\n");
+        writeln!(output, "    pub {ident}: Option<{}>,", attribute.rust_type).unwrap();
+    }
+    output.push_str("}\n\n");
+
+    writeln!(output, "impl ::espocrm_rs::Entity for {struct_name} {{").unwrap();
+    writeln!(output, "    const ENTITY_TYPE: &'static str = {entity_type:?};").unwrap();
+    let fields = attributes.iter().map(|x| format!("{:?}", x.name)).collect::<Vec<_>>().join(", ");
+    writeln!(output, "    const FIELDS: &'static [&'static str] = &[{fields}];").unwrap();
+    output.push_str("}\n");
+}
+
+/// The attributes EspoCRM stores for a field, and their Rust types
+fn field_attributes(struct_name: &str, field_name: &str, field: &FieldDefs, enums: &mut String) -> Vec<Attribute> {
+    let attribute = |suffix: &str, rust_type: &str| Attribute {
+        name: format!("{field_name}{suffix}"),
+        rust_type: rust_type.to_string(),
+    };
+
+    match &field.field_type {
+        // The parts of an address are defined as separate fields
+        FieldType::Address => Vec::new(),
+        FieldType::Link | FieldType::LinkOne | FieldType::File | FieldType::Image => {
+            vec![attribute("Id", "String"), attribute("Name", "String")]
+        }
+        FieldType::LinkParent => vec![
+            attribute("Id", "String"),
+            attribute("Type", "String"),
+            attribute("Name", "String"),
+        ],
+        FieldType::LinkMultiple | FieldType::AttachmentMultiple => vec![
+            attribute("Ids", "Vec<String>"),
+            attribute("Names", "::std::collections::HashMap<String, String>"),
+        ],
+        FieldType::Currency => vec![attribute("", "f64"), attribute("Currency", "String")],
+        FieldType::Enum => match field.options.as_deref() {
+            Some(options) if !options.is_empty() => {
+                let enum_name = format!("{struct_name}{}", to_pascal_case(field_name));
+                generate_enum(enums, &enum_name, struct_name, field_name, options);
+                vec![attribute("", &enum_name)]
+            }
+            _ => vec![attribute("", "String")],
+        },
+        field_type => vec![attribute("", rust_type(field_type))],
+    }
+}
+
+/// The Rust type of a field stored in a single attribute
+fn rust_type(field_type: &FieldType) -> &'static str {
+    match field_type {
+        FieldType::Int | FieldType::AutoIncrement => "i64",
+        FieldType::Float | FieldType::CurrencyConverted => "f64",
+        FieldType::Bool => "bool",
+        FieldType::MultiEnum | FieldType::Array | FieldType::Checklist => "Vec<String>",
+        FieldType::JsonArray => "Vec<::serde_json::Value>",
+        FieldType::JsonObject | FieldType::Foreign | FieldType::Other(_) => "::serde_json::Value",
+        _ => "String",
+    }
+}
+
+fn generate_enum(output: &mut String, enum_name: &str, struct_name: &str, field_name: &str, options: &[String]) {
+    writeln!(output, "/// The options of the `{field_name}` field of [{struct_name}]").unwrap();
+    output.push_str("#[derive(Clone, Debug, Eq, PartialEq, Hash, ::serde::Serialize, ::serde::Deserialize)]\n");
+    writeln!(output, "pub enum {enum_name} {{").unwrap();
+
+    let mut seen = BTreeSet::new();
+    for option in options {
+        let base = match to_pascal_case(option) {
+            x if x.is_empty() => "Empty".to_string(),
+            x => x,
+        };
+
+        let mut variant = base.clone();
+        let mut i = 2;
+        while !seen.insert(variant.clone()) || variant == "Unknown" {
+            variant = format!("{base}{i}");
+            i += 1;
+        }
+
+        writeln!(output, "    #[serde(rename = {option:?})]").unwrap();
+        writeln!(output, "    {variant},").unwrap();
+    }
+
+    output.push_str("    /// A value not present in the metadata this code was generated from, kept as-is so it is sent back unchanged\n");
+    output.push_str("    #[serde(untagged)]\n");
+    output.push_str("    Unknown(String),\n");
+    output.push_str("}\n\n");
+}
+
+/// Convert any string to a PascalCase identifier, dropping all characters which are not alphanumeric
+fn to_pascal_case(input: &str) -> String {
+    let mut output = String::new();
+    for part in input.split(|c: char| !c.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            output.extend(first.to_uppercase());
+            output.extend(chars);
+        }
+    }
+
+    if output.starts_with(|c: char| c.is_ascii_digit()) {
+        output.insert(0, 'V');
+    }
+
+    output
+}
+
+/// Convert a camelCase attribute name to snake_case
+fn to_snake_case(input: &str) -> String {
+    let mut output = String::new();
+    for c in input.chars() {
+        if c.is_ascii_uppercase() {
+            if !output.is_empty() {
+                output.push('_');
+            }
+            output.push(c.to_ascii_lowercase());
+        } else if c.is_ascii_alphanumeric() {
+            output.push(c);
+        } else {
+            output.push('_');
+        }
+    }
+
+    output
+}
+
+/// Convert a snake_case field name to camelCase the same way `#[serde(rename_all = "camelCase")]` does
+fn to_serde_camel_case(input: &str) -> String {
+    let mut output = String::new();
+    let mut capitalize = false;
+    for (i, c) in input.chars().enumerate() {
+        if c == '_' {
+            capitalize = true;
+        } else if capitalize {
+            output.extend(c.to_uppercase());
+            capitalize = false;
+        } else if i == 0 {
+            output.extend(c.to_lowercase());
+        } else {
+            output.push(c);
+        }
+    }
+
+    output
+}
+
+/// Make a snake_case name usable as a Rust identifier
+fn to_identifier(input: &str) -> String {
+    if input.is_empty() || input.starts_with(|c: char| c.is_ascii_digit()) || RESERVED.contains(&input) {
+        format!("_{input}")
+    } else if KEYWORDS.contains(&input) {
+        format!("r#{input}")
+    } else {
+        input.to_string()
+    }
+}
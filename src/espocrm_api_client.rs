@@ -1,13 +1,24 @@
-use crate::espocrm_types::Params;
+use crate::auth::{Auth, Credentials};
+use crate::call::{self, Call};
+use crate::espocrm_error::EspoError;
+use crate::espocrm_metadata::Metadata;
+use crate::espocrm_types::{Entity, ListResponse, PaginationOptions, Params};
+use crate::middleware::{self, EspoRequest, Logging, Middleware, ResponseHead};
+use crate::pagination::PageState;
+use crate::rate_limit::RateLimiter;
+use crate::retry::RetryPolicy;
+use crate::secret::Secret;
+use crate::session::{Session, SessionUser};
+use crate::transport::Transport;
 use crate::{debug_if, trace_if};
-use hmac::{Hmac, Mac};
+use futures::stream::{self, Stream, TryStreamExt};
+use serde::de::DeserializeOwned;
 use serde::Serialize;
-use sha2::Sha256;
-use std::fmt::Debug;
-use reqwest::{Client, RequestBuilder};
-use tap::TapFallible;
-
-type HmacSha256 = Hmac<Sha256>;
+use std::fmt::{self, Debug};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use reqwest::{Client, ClientBuilder};
+use tokio::sync::{Semaphore, SemaphorePermit};
 
 /// Used to indicate the required GenericType is not needed
 /// Used when calling [request()](EspoApiClient::request) with the GET method
@@ -18,6 +29,7 @@ pub enum Method {
     Get,
     Post,
     Put,
+    Patch,
     Delete,
 }
 
@@ -27,19 +39,43 @@ impl From<Method> for reqwest::Method {
             Method::Get => reqwest::Method::GET,
             Method::Post => reqwest::Method::POST,
             Method::Put => reqwest::Method::PUT,
+            Method::Patch => reqwest::Method::PATCH,
             Method::Delete => reqwest::Method::DELETE,
         }
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// Client for the EspoCRM API.
+///
+/// The client holds a pooled [reqwest::Client], so cloning it is cheap and clones share their connections.
+/// Clones also share the budget set with [Self::set_rate_limit] and [Self::set_max_in_flight].
+/// Prefer reusing a single client over creating a new one for every request.
+///
+/// Credentials are zeroed in memory when the client is dropped, and are redacted from its `Debug` output.
+#[derive(Clone)]
 pub struct EspoApiClient {
     pub(crate) url: String,
-    pub(crate) username: Option<String>,
-    pub(crate) password: Option<String>,
-    pub(crate) api_key: Option<String>,
-    pub(crate) secret_key: Option<String>,
+    pub(crate) auth: Auth,
+    pub(crate) middleware: Vec<Arc<dyn Middleware>>,
+    /// Set with the deprecated credential setters, and replaced by [Self::set_auth]
+    pub(crate) credentials: Credentials,
     pub(crate) url_path: String,
+    /// Set with [Self::set_http_client]
+    pub(crate) http_client: Option<Client>,
+    /// Built from the connection options when the first request is sent, unless `http_client` is set
+    pub(crate) built_http_client: Arc<OnceLock<Client>>,
+    /// Replaces the reqwest client if set
+    pub(crate) transport: Option<Arc<dyn Transport>>,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    pub(crate) rate_limiter: Option<Arc<RateLimiter>>,
+    pub(crate) in_flight: Option<Arc<Semaphore>>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) read_timeout: Option<Duration>,
+    pub(crate) timeout: Option<Duration>,
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub(crate) root_certificates: Vec<reqwest::Certificate>,
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub(crate) accept_invalid_certs: bool,
 }
 
 impl EspoApiClient {
@@ -47,11 +83,23 @@ impl EspoApiClient {
     pub fn new(url: &str) -> EspoApiClient {
         EspoApiClient {
             url: url.to_string(),
-            username: None,
-            password: None,
-            api_key: None,
-            secret_key: None,
+            auth: Auth::none(),
+            middleware: Vec::new(),
+            credentials: Credentials::default(),
             url_path: "/api/v1/".to_string(),
+            http_client: None,
+            built_http_client: Arc::default(),
+            transport: None,
+            retry_policy: None,
+            rate_limiter: None,
+            in_flight: None,
+            connect_timeout: None,
+            read_timeout: None,
+            timeout: None,
+            #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+            root_certificates: Vec::new(),
+            #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+            accept_invalid_certs: false,
         }
     }
 
@@ -75,37 +123,216 @@ impl EspoApiClient {
         self
     }
 
+    /// Set the way to authenticate with EspoCRM, replacing any previously set credentials.
+    /// See [Auth] for the available strategies
+    pub fn set_auth(&mut self, auth: Auth) -> &mut EspoApiClient {
+        self.auth = auth;
+        self.credentials = Credentials::default();
+        self
+    }
+
+    /// Add a [Middleware], which runs around every request the client sends.
+    /// Middleware runs in the order it was added, before the built-in authentication, retry and logging middleware
+    pub fn add_middleware<M: Middleware + 'static>(&mut self, middleware: M) -> &mut EspoApiClient {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
     /// Set the username to use for authentication.
     /// If you use this you must also call [`Self::set_password()`]
     /// It is not recommended that you use this. Instead you should use API Key authorization or HMAC
+    #[deprecated(note = "use `set_auth(Auth::basic(username, password))` instead")]
     pub fn set_username<S: AsRef<str>>(&mut self, username: S) -> &mut EspoApiClient {
-        self.username = Some(username.as_ref().to_string());
+        self.credentials.username = Some(username.as_ref().to_string());
+        self.auth = self.credentials.to_auth();
         self
     }
 
     /// Set the password to use for authentication
     /// If you use this you must also call [`Self::set_username()`]
     /// It is not recommended that you use this. Instead you should use API Key authorization or HMAC authorization
+    #[deprecated(note = "use `set_auth(Auth::basic(username, password))` instead")]
     pub fn set_password<S: AsRef<str>>(&mut self, password: S) -> &mut EspoApiClient {
-        self.password = Some(password.as_ref().to_string());
+        self.credentials.password = Some(Secret::new(password.as_ref()));
+        self.auth = self.credentials.to_auth();
         self
     }
 
     /// Set the API Key to use for authorization
     /// If you only provide the API key, and not the secret_key, API Key authorization will be used.
     /// If you wish to use HMAC authorization, you must also call [`Self::set_secret_key()`]
+    #[deprecated(note = "use `set_auth(Auth::api_key(api_key))` or `set_auth(Auth::hmac(api_key, secret_key))` instead")]
     pub fn set_api_key<S: AsRef<str>>(&mut self, api_key: S) -> &mut EspoApiClient {
-        self.api_key = Some(api_key.as_ref().to_string());
+        self.credentials.api_key = Some(Secret::new(api_key.as_ref()));
+        self.auth = self.credentials.to_auth();
         self
     }
 
     /// Set the Secret Key to use for HMAC authorization
     /// If you use this you must also call [`Self::set_api_key()`]
+    #[deprecated(note = "use `set_auth(Auth::hmac(api_key, secret_key))` instead")]
     pub fn set_secret_key<S: AsRef<str>>(&mut self, secret_key: S) -> &mut EspoApiClient {
-        self.secret_key = Some(secret_key.as_ref().to_string());
+        self.credentials.secret_key = Some(Secret::new(secret_key.as_ref()));
+        self.auth = self.credentials.to_auth();
+        self
+    }
+
+    /// Set the [reqwest::Client] used to send requests.
+    /// Use this to configure e.g. proxies.
+    ///
+    /// The connect and read timeouts and TLS options of this crate don't apply to this client, configure them on the client instead.
+    /// This replaces a transport set with [Self::set_transport]
+    pub fn set_http_client(&mut self, http_client: Client) -> &mut EspoApiClient {
+        self.http_client = Some(http_client);
+        self.transport = None;
+        self
+    }
+
+    /// Send requests with `transport` instead of the [reqwest::Client], e.g. a [MemoryTransport](crate::MemoryTransport) in tests.
+    ///
+    /// The connect and read timeouts and TLS options only apply to the reqwest client.
+    /// The timeout set with [Self::set_timeout] is passed to the transport in [EspoRequest::timeout].
+    /// Calling [Self::set_http_client] afterwards replaces this transport
+    pub fn set_transport<T: Transport + 'static>(&mut self, transport: T) -> &mut EspoApiClient {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Set the policy for retrying requests which failed because of a transient error.
+    /// By default requests are not retried. See [RetryPolicy] for the defaults it uses.
+    ///
+    /// Retrying waits using tokio's timer, so the tokio runtime must have its time driver enabled
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) -> &mut EspoApiClient {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    /// Limit the number of requests sent per second, using a token bucket which holds up to `burst` requests.
+    /// Requests over the limit wait until they may be sent. Retries count towards the limit as well.
+    ///
+    /// The limit is shared by all clones of this client made after calling this. A `requests_per_second` of zero or less removes the limit
+    pub fn set_rate_limit(&mut self, requests_per_second: f64, burst: u32) -> &mut EspoApiClient {
+        self.rate_limiter = (requests_per_second > 0.0).then(|| Arc::new(RateLimiter::new(requests_per_second, burst)));
+        self
+    }
+
+    /// Limit the number of requests waiting for a response from EspoCRM at the same time.
+    /// Requests over the limit wait until another request has received its response.
+    ///
+    /// Methods which deserialize the response, such as [Self::get], count a request as in flight until its body has been read.
+    /// Methods which return the [reqwest::Response], such as [Self::read], only count it until the headers have arrived, as the caller reads the body.
+    ///
+    /// The limit is shared by all clones of this client made after calling this. A `max_in_flight` of zero removes the limit
+    pub fn set_max_in_flight(&mut self, max_in_flight: usize) -> &mut EspoApiClient {
+        self.in_flight = (max_in_flight > 0).then(|| Arc::new(Semaphore::new(max_in_flight)));
+        self
+    }
+
+    /// Set the maximum time to wait for a connection to EspoCRM to be established.
+    /// Timeouts are reported as [EspoError::Timeout].
+    ///
+    /// Has no effect on a client set with [Self::set_http_client]
+    pub fn set_connect_timeout(&mut self, timeout: Duration) -> &mut EspoApiClient {
+        self.connect_timeout = Some(timeout);
+        self.built_http_client = Arc::default();
+        self
+    }
+
+    /// Set the maximum time to wait for data from EspoCRM, measured from the last time data was received.
+    /// Unlike [Self::set_timeout] this allows slow downloads to finish, as long as they make progress.
+    /// Timeouts are reported as [EspoError::Timeout].
+    ///
+    /// Has no effect on a client set with [Self::set_http_client]
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> &mut EspoApiClient {
+        self.read_timeout = Some(timeout);
+        self.built_http_client = Arc::default();
+        self
+    }
+
+    /// Set the maximum time a request may take in total, from connecting until the response body has been read.
+    /// Every attempt of a retried request gets the full timeout. Timeouts are reported as [EspoError::Timeout].
+    ///
+    /// Use [Self::with_timeout] to override the timeout for a single request
+    pub fn set_timeout(&mut self, timeout: Duration) -> &mut EspoApiClient {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Get a copy of this client with a different total timeout, to use for a request which is expected to take longer or shorter than usual.
+    /// The copy shares its connections and limits with this client.
+    /// ```rust
+    /// use espocrm_rs::{EspoApiClient, EspoError};
+    /// use std::time::Duration;
+    ///
+    /// async fn export(client: &EspoApiClient) -> Result<reqwest::Response, EspoError> {
+    ///     client.with_timeout(Duration::from_secs(300)).read("Export", "1").await
+    /// }
+    /// ```
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        let mut client = self.clone();
+        client.timeout = Some(timeout);
+        client
+    }
+
+    /// Trust `certificate` as a root certificate, in addition to the system's or bundled ones.
+    /// Use this for an EspoCRM instance with a certificate signed by an internal CA.
+    /// ```rust
+    /// use espocrm_rs::{Certificate, EspoApiClient};
+    ///
+    /// fn client(pem: &[u8]) -> EspoApiClient {
+    ///     EspoApiClient::new("https://crm.internal.example.com")
+    ///         .add_root_certificate(Certificate::from_pem(pem).unwrap())
+    ///         .build()
+    /// }
+    /// ```
+    ///
+    /// Requires the `rustls-tls` or `native-tls` feature. Has no effect on a client set with [Self::set_http_client].
+    /// If the certificate can't be used, requests fail with [EspoError::Transport]
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub fn add_root_certificate(&mut self, certificate: reqwest::Certificate) -> &mut EspoApiClient {
+        self.root_certificates.push(certificate);
+        self.built_http_client = Arc::default();
+        self
+    }
+
+    /// Accept any certificate, including self-signed and expired ones, and certificates for a different host.
+    ///
+    /// **This disables protection against man-in-the-middle attacks.** Only use it for e.g. a staging server,
+    /// and prefer [Self::add_root_certificate] with the server's certificate where possible.
+    ///
+    /// Requires the `rustls-tls` or `native-tls` feature. Has no effect on a client set with [Self::set_http_client]
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub fn set_accept_invalid_certs(&mut self, accept_invalid_certs: bool) -> &mut EspoApiClient {
+        self.accept_invalid_certs = accept_invalid_certs;
+        self.built_http_client = Arc::default();
         self
     }
 
+    /// A [ClientBuilder] with the connection options set on this client.
+    /// The blocking client is built from it as well
+    pub(crate) fn http_client_builder(&self) -> ClientBuilder {
+        let mut builder = Client::builder();
+
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+
+        if let Some(read_timeout) = self.read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        {
+            for certificate in &self.root_certificates {
+                builder = builder.add_root_certificate(certificate.clone());
+            }
+
+            builder = builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+        }
+
+        builder
+    }
+
     pub(crate) fn normalize_url<S: AsRef<str>>(&self, action: S) -> String {
         format!("{}{}{}", self.url, self.url_path, action.as_ref())
     }
@@ -117,22 +344,9 @@ impl EspoApiClient {
     ///
     /// # Errors
     ///
-    /// If the request fails
-    pub async fn create_allow_duplicates<T, S>(&self, action: S, data: T) -> reqwest::Result<reqwest::Response> where T: Serialize + Clone + Debug, S: AsRef<str> {
-        let url = self.normalize_url(&action);
-        let client = Client::new();
-        let mut request = client.post(url);
-        request = self.configure_client_auth(request, reqwest::Method::POST, action.as_ref());
-
-        #[allow(unused)] // `x` in the tap_ functions
-        request
-            .header("X-Skip-Duplicate-Check", "true")
-            .json(&data)
-            .send()
-            .await
-            .tap_err(|x| debug_if!("Got an error from EspoCRM: {x}"))
-            .tap_ok(|x| debug_if!("Got response from EspoCRM with status code: {}", x.status()))
-
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub async fn create_allow_duplicates<T, S>(&self, action: S, data: T) -> Result<reqwest::Response, EspoError> where T: Serialize + Clone + Debug, S: AsRef<str> {
+        self.execute(Call::create_allow_duplicates(action.as_ref(), &data)?).await
     }
 
     /// Make a POST request to EspoCRM to create an entity.
@@ -142,20 +356,10 @@ impl EspoApiClient {
     ///
     /// # Errors
     ///
-    /// If the request fails
-    pub async fn create<T, S>(&self, action: S, data: T) -> reqwest::Result<reqwest::Response> where T: Serialize + Clone + Debug, S: AsRef<str> {
-        let url = self.normalize_url(&action.as_ref());
-        let client = Client::new();
-        let mut request = client.post(url);
-        request = self.configure_client_auth(request, reqwest::Method::POST, action.as_ref());
-
-        #[allow(unused)] // `x` in the tap_ functions
-        request
-            .json(&data)
-            .send()
-            .await
-            .tap_err(|x| debug_if!("Got an error from EspoCRM: {x}"))
-            .tap_ok(|x| debug_if!("Got response from EspoCRM with status code: {}", x.status()))
+    /// If the request fails, or EspoCRM returns a non-success status code.
+    /// Duplicates are reported as [EspoError::Duplicate]
+    pub async fn create<T, S>(&self, action: S, data: T) -> Result<reqwest::Response, EspoError> where T: Serialize + Clone + Debug, S: AsRef<str> {
+        self.execute(Call::create(action.as_ref(), &data)?).await
     }
 
     /// Make a request to EspoCRM
@@ -167,91 +371,518 @@ impl EspoApiClient {
     /// * action: On what EspoCRM Object should the action be performed on. E.g "Contact" or "Contact/ID". Essentially this is everything after "/api/v1/" in the URL.
     /// * data_get: The filter to use on a GET request. Will be serialized according to PHP's http_build_query function.
     /// * data_post: The data to send on everything that is not a GET request. It will be serialized to JSON and send as the request body.
-    #[cfg_attr(feature = "tracing", tracing::instrument(skip(data_get, data_post)))]
+    ///
+    /// # Errors
+    ///
+    /// If `data_get` can't be serialized, the request fails, or EspoCRM returns a non-success status code
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, data_get, data_post)))]
     pub async fn request<T, S>(
         &self,
         method: Method,
         action: S,
         data_get: Option<Params>,
         data_post: Option<T>,
-    ) -> reqwest::Result<reqwest::Response>
+    ) -> Result<reqwest::Response, EspoError>
     where
         T: Serialize + Clone + Debug,
         S: AsRef<str> + Debug,
     {
-        let mut url = self.normalize_url(&action.as_ref());
-        debug_if!("Using URL {url} to request from EspoCRM");
+        self.execute(Call::request(method, action.as_ref(), data_get, data_post.as_ref())?).await
+    }
 
-        let reqwest_method = reqwest::Method::from(method);
+    /// Send `call` to EspoCRM.
+    /// The request passes through the middleware for every attempt, which may retry it
+    async fn execute(&self, call: Call) -> Result<reqwest::Response, EspoError> {
+        // The caller reads the body, so the response no longer counts as in flight
+        let (response, _permit) = self.execute_in_flight(call).await?;
+        Ok(response)
+    }
 
-        url = if data_get.is_some() && reqwest_method == reqwest::Method::GET {
-            format!(
-                "{}?{}",
-                url,
-                crate::serializer::serialize(data_get.unwrap()).unwrap()
-            )
-        } else {
-            url
-        };
+    /// Like [Self::execute], deserializing the response body while it still counts as in flight
+    async fn execute_json<T: DeserializeOwned>(&self, call: Call) -> Result<T, EspoError> {
+        let (response, _permit) = self.execute_in_flight(call).await?;
+        call::deserialize_body(&response.bytes().await?)
+    }
 
-        let client = Client::new();
-        let mut request_builder = client.request(reqwest_method.clone(), url);
-        request_builder = self.configure_client_auth(request_builder, reqwest_method.clone(), action.as_ref());
+    /// Like [Self::execute], returning the permit of the request in flight along with the response
+    async fn execute_in_flight(&self, call: Call) -> Result<(reqwest::Response, Option<SemaphorePermit<'_>>), EspoError> {
+        let (response, permit) = self.send(self.new_request(call)?).await?;
+        Ok((EspoError::check_response(response).await?, permit))
+    }
+
+    /// Send `base_request` through the middleware, for as many attempts as the middleware asks for.
+    /// Logs in first if needed, unless this is the login itself
+    async fn send(&self, base_request: EspoRequest) -> Result<(reqwest::Response, Option<SemaphorePermit<'_>>), EspoError> {
+        let mut attempt = 1;
+
+        loop {
+            if let Some(session) = self.auth.as_session().filter(|_| !base_request.login) {
+                self.ensure_logged_in(session).await?;
+            }
 
-        if data_post.is_some() {
-            if reqwest_method != reqwest::Method::GET {
-                request_builder = request_builder.json(&data_post.clone().unwrap());
-                request_builder = request_builder.header("Content-Type", "application/json");
+            let permit = self.acquire_limits().await;
+            let request = self.prepare_attempt(&base_request, attempt)?;
+            let result = self.transport()?.send(&request).await;
+
+            let head = result.as_ref().map(|x| ResponseHead { status: x.status(), headers: x.headers() });
+            match self.retry_delay(&request, head) {
+                Some(delay) => {
+                    drop(permit);
+                    tokio::time::sleep(delay).await;
+                }
+                None => return Ok((result?, permit)),
             }
+
+            attempt += 1;
+        }
+    }
+
+    /// Wait until a request may be sent within the rate limit and the limit of requests in flight.
+    /// The returned permit counts as a request in flight until it is dropped
+    async fn acquire_limits(&self) -> Option<SemaphorePermit<'_>> {
+        // The semaphore is never closed, so acquiring a permit can't fail
+        let permit = match &self.in_flight {
+            Some(in_flight) => in_flight.acquire().await.ok(),
+            None => None,
+        };
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
         }
 
-        trace_if!("Sending request to EspoCRM");
-        #[allow(unused)]
-        request_builder
-            .send()
-            .await
-            .tap_err(|x| debug_if!("Got an error from EspoCRM: {x}"))
-            .tap_ok(|x| debug_if!("Got response from EspoCRM with status code: {}", x.status()))
-    }
-
-    fn configure_client_auth(&self, mut request_builder: RequestBuilder, request_method: reqwest::Method, action: &str) -> RequestBuilder {
-        //Basic authentication
-        if self.username.is_some() && self.password.is_some() {
-            trace_if!("Using basic authentication");
-            request_builder =
-                request_builder.basic_auth(self.username.clone().unwrap(), self.password.clone());
-
-            //HMAC authentication
-        } else if self.api_key.is_some() && self.secret_key.is_some() {
-            trace_if!("Using HMAC authentication.");
-
-            let str = format!(
-                "{} /{}",
-                request_method.clone().to_string(),
-                action,
-            );
-
-            let mut mac = HmacSha256::new_from_slice(self.secret_key.clone().unwrap().as_bytes())
-                .expect("Unable to create Hmac instance. Is your key valid?");
-            mac.update(str.as_bytes());
-            let mac_result = mac.finalize().into_bytes();
-
-            let auth_part = format!(
-                "{}{}{}",
-                base64::encode(self.api_key.clone().unwrap().as_bytes()),
-                "6", //: in base64, for some reason this works, and turning ':' into base64 does not.
-                base64::encode(mac_result)
-            );
-
-            request_builder = request_builder.header("X-Hmac-Authorization", auth_part);
-
-            //Basic api key authentication
-        } else if self.api_key.is_some() {
-            trace_if!("Authenticating with an API key");
-
-            request_builder = request_builder.header("X-Api-Key", self.api_key.clone().unwrap());
+        permit
+    }
+
+    /// The URL of `action`, with the serialized query string appended
+    pub(crate) fn request_url(&self, action: &str, query: Option<String>) -> String {
+        let mut url = self.normalize_url(action);
+        debug_if!("Using URL {url} to request from EspoCRM");
+
+        if let Some(query) = query {
+            url = format!("{}?{}", url, query);
         }
 
-        request_builder
+        url
     }
-}
\ No newline at end of file
+
+    /// The request for `call` with the timeout of this client, before it has passed through the middleware
+    pub(crate) fn new_request(&self, call: Call) -> Result<EspoRequest, EspoError> {
+        let mut request = EspoRequest::new(call.method, self.request_url(&call.action, call.query), &call.action);
+        request.timeout = self.timeout;
+
+        for (name, value) in call.headers {
+            request.set_header(name, value)?;
+        }
+
+        if let Some(body) = call.body {
+            request.body = Some(body);
+            request.set_header("Content-Type", "application/json")?;
+        }
+
+        Ok(request)
+    }
+
+    /// Attempt number `attempt` of `request`, after it has passed through the middleware.
+    /// The middleware sees a fresh request for every attempt, so e.g. the HMAC signature is regenerated
+    pub(crate) fn prepare_attempt(&self, request: &EspoRequest, attempt: u32) -> Result<EspoRequest, EspoError> {
+        let mut request = EspoRequest { attempt, started_at: Instant::now(), ..request.clone() };
+        middleware::before_send(self.middleware_chain(), &mut request)?;
+
+        Ok(request)
+    }
+
+    /// How long to wait before retrying `request` after the outcome of this attempt, or `None` if it is final
+    pub(crate) fn retry_delay(&self, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> Option<Duration> {
+        middleware::after_receive(self.middleware_chain(), request, response)
+    }
+
+    /// The transport set with [Self::set_transport], or the reqwest client
+    pub(crate) fn transport(&self) -> Result<&dyn Transport, EspoError> {
+        match &self.transport {
+            Some(transport) => Ok(transport.as_ref()),
+            None => Ok(self.http_client()?),
+        }
+    }
+
+    /// The client set with [Self::set_http_client], or the one built from the connection options.
+    /// The built client is shared by all clones made before one of the options is changed
+    fn http_client(&self) -> Result<&Client, EspoError> {
+        if let Some(http_client) = self.http_client.as_ref().or_else(|| self.built_http_client.get()) {
+            return Ok(http_client);
+        }
+
+        let http_client = self.http_client_builder().build().map_err(EspoError::Transport)?;
+        Ok(self.built_http_client.get_or_init(|| http_client))
+    }
+
+    /// The middleware added to the client, followed by the built-in authentication, retry and logging middleware
+    pub(crate) fn middleware_chain(&self) -> impl Iterator<Item = &dyn Middleware> {
+        let retry_policy = self.retry_policy.as_ref().map(|x| x as &dyn Middleware);
+
+        self.middleware
+            .iter()
+            .map(|x| x.as_ref())
+            .chain([&self.auth as &dyn Middleware])
+            .chain(retry_policy)
+            .chain([&Logging as &dyn Middleware])
+    }
+
+    /// Log in with `session` if there is no token
+    async fn ensure_logged_in(&self, session: &Session) -> Result<(), EspoError> {
+        if session.token().is_some() {
+            return Ok(());
+        }
+
+        let _guard = session.login_lock.lock().await;
+        // Another request may have logged in while this one was waiting
+        if session.token().is_some() {
+            return Ok(());
+        }
+
+        self.send_login(session).await.map(drop)
+    }
+
+    async fn send_login(&self, session: &Session) -> Result<SessionUser, EspoError> {
+        trace_if!("Logging in to EspoCRM");
+        let mut response = self.send_login_request(session, None).await?;
+
+        if let Some(second_factor) = session.second_step(response.status(), response.headers()) {
+            let code = second_factor.code_async().await?;
+            response = self.send_login_request(session, Some(&code)).await?;
+        }
+
+        let response = EspoError::check_response(response).await?;
+
+        session.store(&response.bytes().await?)
+    }
+
+    /// Send a login request through the middleware, within the same limits as other requests
+    async fn send_login_request(&self, session: &Session, code: Option<&Secret>) -> Result<reqwest::Response, EspoError> {
+        // Boxed, as sending a request may log in first
+        let (response, _permit) = Box::pin(self.send(self.login_request(session, code)?)).await?;
+        Ok(response)
+    }
+
+    /// The login request of `session`, with the two-factor code if EspoCRM asked for one
+    pub(crate) fn login_request(&self, session: &Session, code: Option<&Secret>) -> Result<EspoRequest, EspoError> {
+        let mut request = self.new_request(Call::login())?;
+        request.login = true;
+        session.authorize_login(&mut request, code)?;
+
+        Ok(request)
+    }
+
+    /// Make a GET request to EspoCRM to read a single record.
+    /// If you want the record deserialized, use [Self::get] instead.
+    ///
+    /// * entity: The type of entity to read. E.g "Contact"
+    /// * id: The ID of the record
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code.
+    /// A record that does not exist is reported as [EspoError::NotFound]
+    pub async fn read<S, I>(&self, entity: S, id: I) -> Result<reqwest::Response, EspoError>
+    where
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::read(entity.as_ref(), id.as_ref())).await
+    }
+
+    /// Make a PATCH request to EspoCRM to update a record.
+    /// Only the attributes present in `data` are changed.
+    ///
+    /// PATCH is supported from EspoCRM 7 onwards. For older versions, use [Self::update_put].
+    ///
+    /// * entity: The type of entity to update. E.g "Contact"
+    /// * id: The ID of the record
+    /// * data: The attributes to update
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub async fn update<T, S, I>(&self, entity: S, id: I, data: T) -> Result<reqwest::Response, EspoError>
+    where
+        T: Serialize + Clone + Debug,
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::update(entity.as_ref(), id.as_ref(), &data)?).await
+    }
+
+    /// Make a PUT request to EspoCRM to update a record.
+    /// Behaves like [Self::update], but uses the PUT method expected by EspoCRM versions before 7.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub async fn update_put<T, S, I>(&self, entity: S, id: I, data: T) -> Result<reqwest::Response, EspoError>
+    where
+        T: Serialize + Clone + Debug,
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::update_put(entity.as_ref(), id.as_ref(), &data)?).await
+    }
+
+    /// Make a DELETE request to EspoCRM to delete a record.
+    ///
+    /// * entity: The type of entity to delete. E.g "Contact"
+    /// * id: The ID of the record
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub async fn delete<S, I>(&self, entity: S, id: I) -> Result<reqwest::Response, EspoError>
+    where
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute(Call::delete(entity.as_ref(), id.as_ref())).await
+    }
+
+    /// Make a POST request to EspoCRM to create a record of entity type `T`, and deserialize the created record.
+    /// This request will perform duplicate checks, see [Self::create].
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the created record could not be deserialized into `T`
+    pub async fn create_entity<T>(&self, data: &T) -> Result<T, EspoError>
+    where
+        T: Entity + Serialize + DeserializeOwned + Debug,
+    {
+        self.execute_json(Call::create(T::ENTITY_TYPE, data)?).await
+    }
+
+    /// Fetch a single record of entity type `T`
+    ///
+    /// * id: The ID of the record
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the record could not be deserialized into `T`
+    pub async fn get<T, I>(&self, id: I) -> Result<T, EspoError>
+    where
+        T: Entity + DeserializeOwned,
+        I: AsRef<str>,
+    {
+        self.get_of(T::ENTITY_TYPE, id).await
+    }
+
+    /// Fetch a single record and deserialize it into `T`.
+    /// Like [Self::get], for entity types without an [Entity] implementation, e.g. with `T` as [serde_json::Value]
+    ///
+    /// * entity: The type of entity to fetch. E.g "Contact"
+    /// * id: The ID of the record
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the record could not be deserialized into `T`
+    pub async fn get_of<T, S, I>(&self, entity: S, id: I) -> Result<T, EspoError>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+        I: AsRef<str>,
+    {
+        self.execute_json(Call::read(entity.as_ref(), id.as_ref())).await
+    }
+
+    /// Make a PATCH request to EspoCRM to update a record of entity type `T`, and deserialize the updated record.
+    /// See [Self::update].
+    ///
+    /// * id: The ID of the record
+    /// * data: The attributes to update
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the updated record could not be deserialized into `T`
+    pub async fn update_entity<T, I>(&self, id: I, data: &T) -> Result<T, EspoError>
+    where
+        T: Entity + Serialize + DeserializeOwned + Debug,
+        I: AsRef<str>,
+    {
+        self.execute_json(Call::update(T::ENTITY_TYPE, id.as_ref(), data)?).await
+    }
+
+    /// Make a DELETE request to EspoCRM to delete a record of entity type `T`.
+    ///
+    /// * id: The ID of the record
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, or EspoCRM returns a non-success status code
+    pub async fn delete_entity<T, I>(&self, id: I) -> Result<(), EspoError>
+    where
+        T: Entity,
+        I: AsRef<str>,
+    {
+        self.delete(T::ENTITY_TYPE, id).await?;
+        Ok(())
+    }
+
+    /// Fetch a list of records of entity type `T`.
+    /// The `params` are used to filter, order and paginate the list.
+    ///
+    /// * params: The filter to apply to the list
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the records could not be deserialized into `T`
+    pub async fn list<T>(&self, params: Params) -> Result<ListResponse<T>, EspoError>
+    where
+        T: Entity + DeserializeOwned,
+    {
+        self.list_of(T::ENTITY_TYPE, params).await
+    }
+
+    /// Fetch a list of records and deserialize them into `T`.
+    /// Like [Self::list], for entity types without an [Entity] implementation
+    ///
+    /// * entity: The type of entity to fetch. E.g "Contact"
+    /// * params: The filter to apply to the list
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the records could not be deserialized into `T`
+    pub async fn list_of<T, S>(&self, entity: S, params: Params) -> Result<ListResponse<T>, EspoError>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+    {
+        self.execute_json(Call::list(entity.as_ref(), params)?).await
+    }
+
+    /// Fetch all records of entity type `T` matching `params` as a [Stream], requesting pages lazily as the stream is consumed.
+    ///
+    /// The `offset` in `params` is used as the starting point. Its `max_size` is ignored in favour of the page size in `options`.
+    /// The stream ends when EspoCRM returns fewer records than the page size, or all records counted in `total` have been returned.
+    ///
+    /// If the data may change while the stream is consumed, use a [StableOrder](crate::StableOrder) in `options` so records aren't skipped or returned twice.
+    ///
+    /// * params: The filter to apply to the list
+    /// * options: The page size and ordering to use
+    ///
+    /// # Errors
+    ///
+    /// The stream yields an error if a request fails, EspoCRM returns a non-success status code, or a record could not be deserialized into `T`.
+    pub fn list_stream<T>(&self, params: Params, options: PaginationOptions) -> impl Stream<Item = Result<T, EspoError>>
+    where
+        T: Entity + DeserializeOwned,
+    {
+        self.list_stream_of(T::ENTITY_TYPE, params, options)
+    }
+
+    /// Fetch all records matching `params` as a [Stream], deserialized into `T`.
+    /// Like [Self::list_stream], for entity types without an [Entity] implementation
+    ///
+    /// * entity: The type of entity to fetch. E.g "Contact"
+    /// * params: The filter to apply to the list
+    /// * options: The page size and ordering to use
+    ///
+    /// # Errors
+    ///
+    /// The stream yields an error if a request fails, EspoCRM returns a non-success status code, or a record could not be deserialized into `T`.
+    pub fn list_stream_of<T, S>(&self, entity: S, params: Params, options: PaginationOptions) -> impl Stream<Item = Result<T, EspoError>>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+    {
+        let client = self.clone();
+        let entity = entity.as_ref().to_string();
+
+        stream::try_unfold(PageState::new(params, options), move |mut state| {
+            let client = client.clone();
+            let entity = entity.clone();
+
+            async move {
+                let params = match state.next_params() {
+                    Some(params) => params,
+                    None => return Ok(None),
+                };
+
+                trace_if!("Fetching page of {entity} from EspoCRM");
+                let page: ListResponse<serde_json::Value> = client.list_of(&entity, params).await?;
+                state.advance(page.total, &page.list)?;
+
+                let records = page.list
+                    .into_iter()
+                    .map(serde_json::from_value)
+                    .collect::<Result<Vec<T>, _>>()?;
+
+                Ok::<_, EspoError>(Some((records, state)))
+            }
+        })
+        .map_ok(|records| stream::iter(records.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// Log in with the credentials of [Auth::session], replacing the cached token.
+    /// Requests log in when needed, so this is only required to check the credentials up front, or to fetch the [SessionUser].
+    ///
+    /// # Errors
+    ///
+    /// If the client does not use [Auth::session], the request fails, or EspoCRM returns a non-success status code.
+    /// Wrong credentials are reported as [EspoError::Unauthorized]
+    pub async fn login(&self) -> Result<SessionUser, EspoError> {
+        let session = self.auth.as_session().ok_or_else(|| EspoError::Auth("the client does not use session authentication".into()))?;
+        let _guard = session.login_lock.lock().await;
+        self.send_login(session).await
+    }
+
+    /// The user logged in with [Auth::session], or `None` if the client has not logged in yet or uses another [Auth]
+    pub fn session_user(&self) -> Option<SessionUser> {
+        self.auth.as_session().and_then(Session::user)
+    }
+
+    /// Fetch EspoCRM's metadata, describing the entities, fields and links configured in EspoCRM.
+    /// The metadata is filtered by EspoCRM to what the authenticated user has access to.
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the metadata could not be deserialized
+    pub async fn metadata(&self) -> Result<Metadata, EspoError> {
+        self.execute_json(Call::metadata()).await
+    }
+
+    /// Fetch a single part of EspoCRM's metadata, such as `entityDefs.Contact`.
+    /// The key is a dot-separated path into the metadata.
+    ///
+    /// EspoCRM only allows administrators to use this endpoint. Other users should use [Self::metadata].
+    ///
+    /// ```rust
+    /// use espocrm_rs::{EntityDefs, EspoApiClient};
+    ///
+    /// async fn contact_defs(client: &EspoApiClient) -> EntityDefs {
+    ///     client.metadata_key("entityDefs.Contact").await.unwrap()
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// If the request fails, EspoCRM returns a non-success status code, or the metadata could not be deserialized into `T`
+    pub async fn metadata_key<T, S>(&self, key: S) -> Result<T, EspoError>
+    where
+        T: DeserializeOwned,
+        S: AsRef<str>,
+    {
+        self.execute_json(Call::metadata_key(key.as_ref())).await
+    }
+}
+
+impl Debug for EspoApiClient {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Credentials are redacted by `Secret`
+        f.debug_struct("EspoApiClient")
+            .field("url", &self.url)
+            .field("auth", &self.auth)
+            .field("middleware", &self.middleware)
+            .field("transport", &self.transport)
+            .field("url_path", &self.url_path)
+            .field("retry_policy", &self.retry_policy)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("read_timeout", &self.read_timeout)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,243 @@
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A date, formatted the way EspoCRM expects it: `YYYY-MM-DD`.
+///
+/// With the `chrono` or `time` feature enabled, this can be converted from `chrono::NaiveDate` and `time::Date` with [TryFrom],
+/// which fails for years outside of 0 to 9999.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct EspoDate {
+    year: i32,
+    month: u8,
+    day: u8,
+}
+
+/// A date and time in UTC, formatted the way EspoCRM expects it: `YYYY-MM-DD HH:MM:SS`.
+///
+/// With the `chrono` or `time` feature enabled, this can be converted from `chrono::DateTime`, `chrono::NaiveDateTime`,
+/// `time::OffsetDateTime` and `time::PrimitiveDateTime` with [TryFrom], which fails for years outside of 0 to 9999.
+/// Values with a timezone are converted to UTC, values without one are assumed to be in UTC.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct EspoDateTime {
+    date: EspoDate,
+    hour: u8,
+    minute: u8,
+    second: u8,
+}
+
+/// Returned when parsing or constructing an invalid [EspoDate] or [EspoDateTime]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InvalidDate(String);
+
+impl fmt::Display for InvalidDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid date: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidDate {}
+
+fn is_leap_year(year: i32) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i32, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+impl EspoDate {
+    /// Create a date. Returns `None` if the date does not exist
+    pub fn new(year: i32, month: u8, day: u8) -> Option<Self> {
+        if (0..=9999).contains(&year) && (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month) {
+            Some(Self { year, month, day })
+        } else {
+            None
+        }
+    }
+
+    pub fn year(&self) -> i32 {
+        self.year
+    }
+
+    pub fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub fn day(&self) -> u8 {
+        self.day
+    }
+}
+
+impl EspoDateTime {
+    /// Create a date and time in UTC. Returns `None` if the time does not exist
+    pub fn new(date: EspoDate, hour: u8, minute: u8, second: u8) -> Option<Self> {
+        if hour < 24 && minute < 60 && second < 60 {
+            Some(Self {
+                date,
+                hour,
+                minute,
+                second,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn date(&self) -> EspoDate {
+        self.date
+    }
+
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    pub fn second(&self) -> u8 {
+        self.second
+    }
+}
+
+impl fmt::Display for EspoDate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+impl fmt::Display for EspoDateTime {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:02}:{:02}:{:02}", self.date, self.hour, self.minute, self.second)
+    }
+}
+
+/// Parse `N-N-N` or `N:N:N` into its three parts
+fn parse_parts<T: FromStr>(input: &str, separator: char) -> Option<(T, u8, u8)> {
+    let mut parts = input.split(separator);
+    let a = parts.next()?.parse().ok()?;
+    let b = parts.next()?.parse().ok()?;
+    let c = parts.next()?.parse().ok()?;
+
+    match parts.next() {
+        Some(_) => None,
+        None => Some((a, b, c)),
+    }
+}
+
+impl FromStr for EspoDate {
+    type Err = InvalidDate;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_parts(s, '-')
+            .and_then(|(year, month, day)| Self::new(year, month, day))
+            .ok_or_else(|| InvalidDate(s.to_string()))
+    }
+}
+
+impl FromStr for EspoDateTime {
+    type Err = InvalidDate;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (date, time) = s.split_once(' ').ok_or_else(|| InvalidDate(s.to_string()))?;
+        let date = date.parse()?;
+
+        parse_parts(time, ':')
+            .and_then(|(hour, minute, second)| Self::new(date, hour, minute, second))
+            .ok_or_else(|| InvalidDate(s.to_string()))
+    }
+}
+
+/// Implements Serialize and Deserialize using the type's Display and FromStr implementations
+macro_rules! serde_string {
+    ($($name:ident),*) => {
+        $(
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+                }
+            }
+        )*
+    };
+}
+
+serde_string!(EspoDate, EspoDateTime);
+
+#[cfg(feature = "chrono")]
+mod chrono_impl {
+    use super::{EspoDate, EspoDateTime, InvalidDate};
+    use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+
+    impl TryFrom<NaiveDate> for EspoDate {
+        type Error = InvalidDate;
+
+        fn try_from(value: NaiveDate) -> Result<Self, Self::Error> {
+            Self::new(value.year(), value.month() as u8, value.day() as u8).ok_or_else(|| InvalidDate(value.to_string()))
+        }
+    }
+
+    /// The value is assumed to be in UTC
+    impl TryFrom<NaiveDateTime> for EspoDateTime {
+        type Error = InvalidDate;
+
+        fn try_from(value: NaiveDateTime) -> Result<Self, Self::Error> {
+            // Leap seconds are represented as a second value of 59 with extra nanoseconds
+            Self::new(value.date().try_into()?, value.hour() as u8, value.minute() as u8, value.second() as u8)
+                .ok_or_else(|| InvalidDate(value.to_string()))
+        }
+    }
+
+    impl<Tz: TimeZone> TryFrom<chrono::DateTime<Tz>> for EspoDateTime {
+        type Error = InvalidDate;
+
+        fn try_from(value: chrono::DateTime<Tz>) -> Result<Self, Self::Error> {
+            value.with_timezone(&Utc).naive_utc().try_into()
+        }
+    }
+}
+
+#[cfg(feature = "time")]
+mod time_impl {
+    use super::{EspoDate, EspoDateTime, InvalidDate};
+    use time::{Date, OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+    impl TryFrom<Date> for EspoDate {
+        type Error = InvalidDate;
+
+        fn try_from(value: Date) -> Result<Self, Self::Error> {
+            Self::new(value.year(), value.month() as u8, value.day()).ok_or_else(|| InvalidDate(value.to_string()))
+        }
+    }
+
+    /// The value is assumed to be in UTC
+    impl TryFrom<PrimitiveDateTime> for EspoDateTime {
+        type Error = InvalidDate;
+
+        fn try_from(value: PrimitiveDateTime) -> Result<Self, Self::Error> {
+            Self::new(value.date().try_into()?, value.hour(), value.minute(), value.second())
+                .ok_or_else(|| InvalidDate(value.to_string()))
+        }
+    }
+
+    impl TryFrom<OffsetDateTime> for EspoDateTime {
+        type Error = InvalidDate;
+
+        fn try_from(value: OffsetDateTime) -> Result<Self, Self::Error> {
+            // Converting to UTC can move the date past the range `time` supports
+            let utc = value.checked_to_offset(UtcOffset::UTC).ok_or_else(|| InvalidDate(value.to_string()))?;
+            PrimitiveDateTime::new(utc.date(), utc.time()).try_into()
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::espocrm_types::FilterType;
+use reqwest::StatusCode;
+use std::fmt;
+
+/// Name of the header EspoCRM uses to explain why a request failed
+pub const STATUS_REASON_HEADER: &str = "X-Status-Reason";
+
+/// Errors returned by the [EspoApiClient](crate::EspoApiClient)
+#[derive(Debug)]
+pub enum EspoError {
+    /// The request could not be sent, or the response could not be read
+    Transport(reqwest::Error),
+    /// Connecting, waiting for a response, or reading it took longer than the configured timeout
+    Timeout(reqwest::Error),
+    /// EspoCRM did not accept the provided credentials. HTTP `401`
+    Unauthorized { reason: Option<String> },
+    /// The authenticated user is not allowed to perform this action. HTTP `403`
+    Forbidden { reason: Option<String> },
+    /// The requested record or endpoint does not exist. HTTP `404`
+    NotFound { reason: Option<String> },
+    /// EspoCRM found one or more duplicates of the record being created. HTTP `409`
+    Duplicate {
+        reason: Option<String>,
+        /// The conflicting records, as returned by EspoCRM
+        records: Vec<serde_json::Value>,
+    },
+    /// The request was malformed or did not pass validation. HTTP `400`
+    Validation {
+        reason: Option<String>,
+        /// The response body, if EspoCRM returned JSON
+        body: Option<serde_json::Value>,
+    },
+    /// EspoCRM failed to process the request. HTTP `5xx`
+    Server {
+        status: StatusCode,
+        reason: Option<String>,
+    },
+    /// Any other non-success status code
+    UnexpectedStatus {
+        status: StatusCode,
+        reason: Option<String>,
+    },
+    /// The response body could not be deserialized into the requested type
+    Deserialize(serde_json::Error),
+    /// The [Params](crate::Params) of a GET request could not be serialized into a query string, or the body of a request could not be serialized to JSON
+    Serialize(SerializeError),
+    /// A custom [AuthProvider](crate::AuthProvider) failed to provide credentials. The request was not sent
+    Auth(Box<dyn std::error::Error + Send + Sync>),
+    /// A [Middleware](crate::Middleware) failed to prepare the request. The request was not sent
+    Middleware(Box<dyn std::error::Error + Send + Sync>),
+    /// A custom [Transport](crate::Transport) failed to send the request, e.g. a [MemoryTransport](crate::MemoryTransport) with no responses left
+    CustomTransport(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl EspoError {
+    /// Convert a response into an error if its status code is not a success.
+    /// Successful responses are returned as-is.
+    pub(crate) async fn check_response(
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response, EspoError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        // Keep the status if the body can't be read
+        let reason = Self::status_reason_header(response.headers());
+        let body = response.text().await.unwrap_or_default();
+
+        Err(Self::from_status(status, reason, &body))
+    }
+
+    /// Like [Self::check_response], for responses of the blocking client
+    #[cfg(feature = "blocking")]
+    pub(crate) fn check_blocking_response(
+        response: reqwest::blocking::Response,
+    ) -> Result<reqwest::blocking::Response, EspoError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let reason = Self::status_reason_header(response.headers());
+        let body = response.text().unwrap_or_default();
+
+        Err(Self::from_status(status, reason, &body))
+    }
+
+    fn status_reason_header(headers: &reqwest::header::HeaderMap) -> Option<String> {
+        headers
+            .get(STATUS_REASON_HEADER)
+            .and_then(|x| x.to_str().ok())
+            .map(|x| x.to_string())
+    }
+
+    /// Build the error matching the status code of a failed response
+    pub(crate) fn from_status(status: StatusCode, reason: Option<String>, body: &str) -> Self {
+        let json = serde_json::from_str::<serde_json::Value>(body).ok();
+
+        match status {
+            StatusCode::BAD_REQUEST => Self::Validation { reason, body: json },
+            StatusCode::UNAUTHORIZED => Self::Unauthorized { reason },
+            StatusCode::FORBIDDEN => Self::Forbidden { reason },
+            StatusCode::NOT_FOUND => Self::NotFound { reason },
+            StatusCode::CONFLICT => {
+                // EspoCRM < 8 returns the duplicates as a bare array,
+                // later versions wrap them in the `data` field
+                let records = match json {
+                    Some(serde_json::Value::Array(records)) => records,
+                    Some(serde_json::Value::Object(mut object)) => match object.remove("data") {
+                        Some(serde_json::Value::Array(records)) => records,
+                        _ => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                };
+
+                Self::Duplicate { reason, records }
+            }
+            status if status.is_server_error() => Self::Server { status, reason },
+            status => Self::UnexpectedStatus { status, reason },
+        }
+    }
+
+    /// The HTTP status code returned by EspoCRM, if a response was received
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Transport(e) | Self::Timeout(e) => e.status(),
+            Self::Deserialize(_) | Self::Serialize(_) | Self::Auth(_) | Self::Middleware(_) | Self::CustomTransport(_) => None,
+            Self::Unauthorized { .. } => Some(StatusCode::UNAUTHORIZED),
+            Self::Forbidden { .. } => Some(StatusCode::FORBIDDEN),
+            Self::NotFound { .. } => Some(StatusCode::NOT_FOUND),
+            Self::Duplicate { .. } => Some(StatusCode::CONFLICT),
+            Self::Validation { .. } => Some(StatusCode::BAD_REQUEST),
+            Self::Server { status, .. } | Self::UnexpectedStatus { status, .. } => Some(*status),
+        }
+    }
+
+    /// The value of the `X-Status-Reason` header returned by EspoCRM, if any
+    pub fn status_reason(&self) -> Option<&str> {
+        match self {
+            Self::Transport(_)
+            | Self::Timeout(_)
+            | Self::Deserialize(_)
+            | Self::Serialize(_)
+            | Self::Auth(_)
+            | Self::Middleware(_)
+            | Self::CustomTransport(_) => None,
+            Self::Unauthorized { reason }
+            | Self::Forbidden { reason }
+            | Self::NotFound { reason }
+            | Self::Duplicate { reason, .. }
+            | Self::Validation { reason, .. }
+            | Self::Server { reason, .. }
+            | Self::UnexpectedStatus { reason, .. } => reason.as_deref(),
+        }
+    }
+}
+
+impl fmt::Display for EspoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "Failed to communicate with EspoCRM: {e}")?,
+            Self::Timeout(e) => write!(f, "The request to EspoCRM timed out: {e}")?,
+            Self::Unauthorized { .. } => write!(f, "EspoCRM rejected the provided credentials")?,
+            Self::Forbidden { .. } => write!(f, "Access to this resource is forbidden")?,
+            Self::NotFound { .. } => write!(f, "The requested resource was not found")?,
+            Self::Duplicate { records, .. } => {
+                write!(f, "EspoCRM found {} duplicate record(s)", records.len())?
+            }
+            Self::Validation { .. } => write!(f, "EspoCRM rejected the request as invalid")?,
+            Self::Server { status, .. } => write!(f, "EspoCRM returned a server error: {status}")?,
+            Self::UnexpectedStatus { status, .. } => {
+                write!(f, "EspoCRM returned an unexpected status: {status}")?
+            }
+            Self::Deserialize(e) => write!(f, "Failed to deserialize the response: {e}")?,
+            Self::Serialize(e) => write!(f, "Failed to serialize the query: {e}")?,
+            Self::Auth(e) => write!(f, "Failed to provide credentials: {e}")?,
+            Self::Middleware(e) => write!(f, "Failed to prepare the request: {e}")?,
+            Self::CustomTransport(e) => write!(f, "Failed to send the request: {e}")?,
+        }
+
+        if let Some(reason) = self.status_reason() {
+            write!(f, " ({reason})")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for EspoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transport(e) | Self::Timeout(e) => Some(e),
+            Self::Deserialize(e) => Some(e),
+            Self::Serialize(e) => Some(e),
+            Self::Auth(e) | Self::Middleware(e) | Self::CustomTransport(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<SerializeError> for EspoError {
+    fn from(e: SerializeError) -> Self {
+        Self::Serialize(e)
+    }
+}
+
+impl From<serde_json::Error> for EspoError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Deserialize(e)
+    }
+}
+
+impl From<reqwest::Error> for EspoError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(e)
+        } else {
+            Self::Transport(e)
+        }
+    }
+}
+
+/// Errors returned when [Params](crate::Params) can't be serialized into a query string
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SerializeError {
+    /// A where clause which requires an attribute has an empty attribute
+    MissingAttribute(FilterType),
+    /// [FilterType::Or] was used in a [Where::Clause](crate::Where::Clause) instead of a [Where::Group](crate::Where::Group)
+    InvalidGroup(FilterType),
+    /// A float value is NaN or infinite. Contains the key of the value
+    NonFiniteFloat(String),
+    /// The body of a request could not be serialized to JSON. Contains the error message of serde_json
+    Body(String),
+}
+
+impl fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::MissingAttribute(filter_type) => write!(f, "A where clause of type {filter_type} requires an attribute"),
+            Self::InvalidGroup(filter_type) => {
+                write!(f, "A where clause of type {filter_type} must be a group of nested clauses")
+            }
+            Self::NonFiniteFloat(key) => write!(f, "The float at {key} is NaN or infinite"),
+            Self::Body(message) => write!(f, "The body could not be serialized to JSON: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {}
@@ -0,0 +1,237 @@
+use crate::espocrm_date::{EspoDate, EspoDateTime};
+use crate::espocrm_types::{FilterType, Value, Where};
+
+/// Start building a where clause on `attribute`.
+///
+/// Each method on the returned [Filter] produces a [Where] of the matching [FilterType], and only accepts the values that filter needs:
+/// a single [ScalarValue], a list of them, a number of days or nothing. Use [Where::new] to build a clause with any other [Value].
+/// ```rust
+/// use espocrm_rs::{filter, or, Params};
+///
+/// let params = Params::new()
+///     .set_where([
+///         or([filter("status").eq("New"), filter("assignedUserId").eq("1")]),
+///         filter("amount").between(100, 500),
+///         filter("createdAt").last_x_days(7),
+///         filter("tags").any_of(["a", "b"]),
+///         filter("isActive").is_true(),
+///     ])
+///     .build();
+/// ```
+pub fn filter<S: Into<String>>(attribute: S) -> Filter {
+    Filter {
+        attribute: attribute.into(),
+    }
+}
+
+/// A group of clauses of which at least one must match
+pub fn or<I: IntoIterator<Item = Where>>(clauses: I) -> Where {
+    Where::or(clauses.into_iter().collect())
+}
+
+/// A group of clauses which must all match
+pub fn and<I: IntoIterator<Item = Where>>(clauses: I) -> Where {
+    Where::and(clauses.into_iter().collect())
+}
+
+/// A group of clauses which must not match
+pub fn not<I: IntoIterator<Item = Where>>(clauses: I) -> Where {
+    Where::not(clauses.into_iter().collect())
+}
+
+/// A single value an attribute can be compared with: a string, number, bool, date or date and time.
+///
+/// The methods of [Filter] take these instead of any [Value], so that a filter can't be given an array or null where it expects a single value.
+/// Filters on lists, such as [Filter::in_list], take an iterator of them.
+/// ```compile_fail
+/// use espocrm_rs::{filter, Value};
+///
+/// filter("status").eq(Value::Null);
+/// ```
+pub trait ScalarValue: Into<Value> {}
+
+/// Implements [ScalarValue] for types which convert into a single [Value]
+macro_rules! scalar_value {
+    ($($ty:ty),*) => {
+        $(
+            impl ScalarValue for $ty {}
+        )*
+    };
+}
+
+scalar_value!(&str, String, i64, i32, bool, f64, EspoDate, EspoDateTime);
+
+/// Builder for a where clause on a single attribute, see [filter]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Filter {
+    attribute: String,
+}
+
+/// Generates methods for filters which take no value
+macro_rules! no_value {
+    ($($(#[$meta:meta])* $name:ident => $filter_type:ident,)*) => {
+        $(
+            $(#[$meta])*
+            pub fn $name(self) -> Where {
+                self.build(FilterType::$filter_type, None)
+            }
+        )*
+    };
+}
+
+/// Generates methods for filters which take a single value
+macro_rules! single_value {
+    ($($(#[$meta:meta])* $name:ident => $filter_type:ident,)*) => {
+        $(
+            $(#[$meta])*
+            pub fn $name<V: ScalarValue>(self, value: V) -> Where {
+                self.build(FilterType::$filter_type, Some(value.into()))
+            }
+        )*
+    };
+}
+
+/// Generates methods for filters which take a list of values
+macro_rules! list_value {
+    ($($(#[$meta:meta])* $name:ident => $filter_type:ident,)*) => {
+        $(
+            $(#[$meta])*
+            pub fn $name<I, V>(self, values: I) -> Where
+            where
+                I: IntoIterator<Item = V>,
+                V: ScalarValue,
+            {
+                self.build(FilterType::$filter_type, Some(Value::array(values.into_iter().map(Into::into).collect())))
+            }
+        )*
+    };
+}
+
+/// Generates methods for filters which take a number of days
+macro_rules! days_value {
+    ($($(#[$meta:meta])* $name:ident => $filter_type:ident,)*) => {
+        $(
+            $(#[$meta])*
+            pub fn $name(self, days: i64) -> Where {
+                self.build(FilterType::$filter_type, Some(Value::int(days)))
+            }
+        )*
+    };
+}
+
+impl Filter {
+    fn build(self, filter_type: FilterType, value: Option<Value>) -> Where {
+        Where::Clause {
+            r#type: filter_type,
+            attribute: self.attribute,
+            value,
+        }
+    }
+
+    /// The value is between `from` and `to`
+    pub fn between<V: ScalarValue>(self, from: V, to: V) -> Where {
+        self.build(FilterType::Between, Some(Value::array(vec![from.into(), to.into()])))
+    }
+
+    single_value! {
+        /// The value equals `value`
+        eq => Equals,
+        /// The value does not equal `value`
+        ne => NotEquals,
+        /// The value is greater than `value`
+        gt => GreaterThan,
+        /// The value is less than `value`
+        lt => LessThan,
+        /// The value is greater than or equal to `value`
+        gte => GreaterThanOrEquals,
+        /// The value is less than or equal to `value`
+        lte => LessThanOrEquals,
+        /// The value contains `value`
+        contains => Contains,
+        /// The value does not contain `value`
+        not_contains => NotContains,
+        /// The value starts with `value`
+        starts_with => StartsWith,
+        /// The value ends with `value`
+        ends_with => EndsWith,
+        /// The value matches the SQL LIKE pattern `value`
+        like => Like,
+        /// The value does not match the SQL LIKE pattern `value`
+        not_like => NotLike,
+    }
+
+    list_value! {
+        /// The value is one of `values`
+        in_list => In,
+        /// The value is none of `values`
+        not_in_list => NotIn,
+        /// The link is linked with one of the records with the ids in `values`
+        linked_with => LinkedWith,
+        /// The link is not linked with any of the records with the ids in `values`
+        not_linked_with => NotLinkedWith,
+        /// The array contains any of `values`
+        any_of => ArrayAnyOf,
+        /// The array contains none of `values`
+        none_of => ArrayNoneOf,
+        /// The array contains all of `values`
+        all_of => ArrayAllOf,
+    }
+
+    days_value! {
+        /// The date is within the last `days` days
+        last_x_days => LastXDays,
+        /// The date is within the next `days` days
+        next_x_days => NextXDays,
+        /// The date is more than `days` days ago
+        older_than_x_days => OlderThanXDays,
+        /// The date is more than `days` days from now
+        after_x_days => AfterXDays,
+    }
+
+    no_value! {
+        /// The value is null
+        is_null => IsNull,
+        /// The value is not null
+        is_not_null => IsNotNull,
+        /// The value is true
+        is_true => IsTrue,
+        /// The value is false
+        is_false => IsFalse,
+        /// The link is linked with any record
+        is_linked => IsLinked,
+        /// The link is not linked with any record
+        is_not_linked => IsNotLinked,
+        /// The array is empty
+        array_is_empty => ArrayIsEmpty,
+        /// The array is not empty
+        array_is_not_empty => ArrayIsNotEmpty,
+        /// The date is in the past
+        past => Past,
+        /// The date is in the future
+        future => Future,
+        /// The date is within the last seven days
+        last_seven_days => LastSevenDays,
+        /// The date is in the current month
+        current_month => CurrentMonth,
+        /// The date is in the last month
+        last_month => LastMonth,
+        /// The date is in the next month
+        next_month => NextMonth,
+        /// The date is in the current quarter
+        current_quarter => CurrentQuarter,
+        /// The date is in the last quarter
+        last_quarter => LastQuarter,
+        /// The date is in the current year
+        current_year => CurrentYear,
+        /// The date is in the last year
+        last_year => LastYear,
+        /// The date is in the current fiscal year
+        current_fiscal_year => CurrentFiscalYear,
+        /// The date is in the last fiscal year
+        last_fiscal_year => LastFiscalYear,
+        /// The date is in the current fiscal quarter
+        current_fiscal_quarter => CurrentFiscalQuarter,
+        /// The date is in the last fiscal quarter
+        last_fiscal_quarter => LastFiscalQuarter,
+    }
+}
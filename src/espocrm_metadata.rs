@@ -0,0 +1,320 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// EspoCRM's metadata, as returned by `GET /api/v1/Metadata`.
+/// Only the parts describing the schema of entities are modelled
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    /// The definitions of all entities, keyed by entity type
+    #[serde(default)]
+    pub entity_defs: BTreeMap<String, EntityDefs>,
+}
+
+impl Metadata {
+    /// The definition of an entity type, if it exists
+    pub fn entity<S: AsRef<str>>(&self, entity_type: S) -> Option<&EntityDefs> {
+        self.entity_defs.get(entity_type.as_ref())
+    }
+}
+
+/// The definition of a single entity type
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct EntityDefs {
+    /// The fields of the entity, keyed by field name
+    #[serde(default)]
+    pub fields: BTreeMap<String, FieldDefs>,
+    /// The relationships of the entity, keyed by link name
+    #[serde(default)]
+    pub links: BTreeMap<String, LinkDefs>,
+}
+
+/// What a payload is validated for, see [EntityDefs::validate]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValidationMode {
+    /// The payload creates a new record, so all required fields must be present
+    Create,
+    /// The payload updates an existing record, so only the attributes present are checked
+    Update,
+}
+
+impl EntityDefs {
+    /// Validate a JSON payload against the field definitions of this entity, before sending it to EspoCRM.
+    ///
+    /// This checks required fields, the maximum length of strings, the options of enum fields and the JSON type of numbers and booleans.
+    /// Link fields are checked through the attributes they are sent as: `accountId` for the `account` link,
+    /// `parentId` and `parentType` for the `parent` link, and `teamsIds` for the `teams` link.
+    ///
+    /// # Errors
+    ///
+    /// All fields that did not pass validation
+    pub fn validate(&self, data: &serde_json::Value, mode: ValidationMode) -> Result<(), Vec<FieldError>> {
+        let empty = serde_json::Map::new();
+        let data = data.as_object().unwrap_or(&empty);
+        let mut errors = Vec::new();
+
+        for (name, field) in &self.fields {
+            if field.disabled {
+                continue;
+            }
+
+            let error = |kind| FieldError {
+                field: name.clone(),
+                kind,
+            };
+
+            if let Some(attributes) = field.link_attributes(name) {
+                let values: Vec<_> = attributes.iter().map(|x| data.get(x).filter(|x| !x.is_null())).collect();
+                if values.iter().any(Option::is_none) && field.required && mode == ValidationMode::Create {
+                    errors.push(error(FieldErrorKind::Required));
+                }
+
+                if values.into_iter().flatten().any(|x| !field.is_link_value(x)) {
+                    errors.push(error(FieldErrorKind::InvalidType(field.field_type.clone())));
+                }
+                continue;
+            }
+
+            let value = match data.get(name) {
+                Some(serde_json::Value::Null) | None => {
+                    if field.required && mode == ValidationMode::Create {
+                        errors.push(error(FieldErrorKind::Required));
+                    }
+                    continue;
+                }
+                Some(value) => value,
+            };
+
+            if let Some(kind) = field.check_value(value) {
+                errors.push(error(kind));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// The definition of a field
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldDefs {
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    #[serde(default)]
+    pub required: bool,
+    /// The allowed values of `enum`, `multiEnum`, `array` and `checklist` fields
+    pub options: Option<Vec<String>>,
+    pub max_length: Option<u32>,
+    #[serde(default)]
+    pub not_storable: bool,
+    #[serde(default)]
+    pub disabled: bool,
+    #[serde(default)]
+    pub read_only: bool,
+    /// Any other parameters of the field
+    #[serde(flatten)]
+    pub params: BTreeMap<String, serde_json::Value>,
+}
+
+impl FieldDefs {
+    /// The attributes a link field is sent as, e.g. `accountId` for the `account` link. `None` for other fields
+    fn link_attributes(&self, name: &str) -> Option<Vec<String>> {
+        let suffixes: &[&str] = match self.field_type {
+            FieldType::Link | FieldType::LinkOne | FieldType::File | FieldType::Image => &["Id"],
+            FieldType::LinkParent => &["Id", "Type"],
+            FieldType::LinkMultiple | FieldType::AttachmentMultiple => &["Ids"],
+            _ => return None,
+        };
+
+        Some(suffixes.iter().map(|x| format!("{name}{x}")).collect())
+    }
+
+    /// Whether a non-null value has the type of the attributes of a link field: a list of ids, or a single id or entity type
+    fn is_link_value(&self, value: &serde_json::Value) -> bool {
+        match self.field_type {
+            FieldType::LinkMultiple | FieldType::AttachmentMultiple => {
+                value.as_array().is_some_and(|x| x.iter().all(serde_json::Value::is_string))
+            }
+            _ => value.is_string(),
+        }
+    }
+
+    /// Check a non-null value against this definition
+    fn check_value(&self, value: &serde_json::Value) -> Option<FieldErrorKind> {
+        let invalid_type = || Some(FieldErrorKind::InvalidType(self.field_type.clone()));
+
+        match &self.field_type {
+            FieldType::Int | FieldType::AutoIncrement if !value.is_i64() && !value.is_u64() => invalid_type(),
+            FieldType::Float | FieldType::Currency | FieldType::CurrencyConverted if !value.is_number() => invalid_type(),
+            FieldType::Bool if !value.is_boolean() => invalid_type(),
+            FieldType::Enum => match (value.as_str(), &self.options) {
+                (None, _) => invalid_type(),
+                (Some(value), Some(options)) if !options.is_empty() && !options.iter().any(|x| x == value) => {
+                    Some(FieldErrorKind::InvalidOption(value.to_string()))
+                }
+                _ => None,
+            },
+            FieldType::MultiEnum | FieldType::Array | FieldType::Checklist => match (value.as_array(), &self.options) {
+                (None, _) => invalid_type(),
+                (Some(values), Some(options)) if !options.is_empty() => values
+                    .iter()
+                    .filter_map(|x| x.as_str())
+                    .find(|x| !options.iter().any(|option| option == x))
+                    .map(|x| FieldErrorKind::InvalidOption(x.to_string())),
+                _ => None,
+            },
+            _ => match (value.as_str(), self.max_length) {
+                (Some(value), Some(max_length)) if value.chars().count() > max_length as usize => {
+                    Some(FieldErrorKind::TooLong { max_length })
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+/// A field which did not pass [validation](EntityDefs::validate)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldError {
+    /// The name of the field
+    pub field: String,
+    pub kind: FieldErrorKind,
+}
+
+/// Why a field did not pass [validation](EntityDefs::validate)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FieldErrorKind {
+    /// The field is required, but missing or null
+    Required,
+    /// The value is longer than the maximum length of the field
+    TooLong { max_length: u32 },
+    /// The value is not one of the options of the field
+    InvalidOption(String),
+    /// The value has the wrong type for the field
+    InvalidType(FieldType),
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            FieldErrorKind::Required => write!(f, "Field '{}' is required", self.field),
+            FieldErrorKind::TooLong { max_length } => {
+                write!(f, "Field '{}' is longer than {} characters", self.field, max_length)
+            }
+            FieldErrorKind::InvalidOption(value) => {
+                write!(f, "Field '{}' does not allow the value '{}'", self.field, value)
+            }
+            FieldErrorKind::InvalidType(field_type) => {
+                write!(f, "Field '{}' has the wrong type, expected {}", self.field, String::from(field_type.clone()))
+            }
+        }
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+/// The definition of a relationship between two entities
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkDefs {
+    #[serde(rename = "type")]
+    pub link_type: LinkType,
+    /// The entity type on the other side of the relationship
+    pub entity: Option<String>,
+    /// The name of the link on the other side of the relationship
+    pub foreign: Option<String>,
+    /// The entity types a `belongsToParent` link may refer to
+    pub entity_list: Option<Vec<String>>,
+    /// Any other parameters of the link
+    #[serde(flatten)]
+    pub params: BTreeMap<String, serde_json::Value>,
+}
+
+/// Declares a string-backed enum, with a catch-all variant for values unknown to this crate
+macro_rules! string_enum {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident => $value:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Clone, Debug, Eq, PartialEq, Hash, Deserialize, Serialize)]
+        #[serde(from = "String", into = "String")]
+        pub enum $name {
+            $($variant,)*
+            /// A value not known to this crate, e.g. from an extension
+            Other(String),
+        }
+
+        impl From<String> for $name {
+            fn from(value: String) -> Self {
+                match value.as_str() {
+                    $($value => Self::$variant,)*
+                    _ => Self::Other(value),
+                }
+            }
+        }
+
+        impl From<$name> for String {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => $value.to_string(),)*
+                    $name::Other(value) => value,
+                }
+            }
+        }
+    };
+}
+
+string_enum! {
+    /// The type of a field
+    FieldType {
+        Varchar => "varchar",
+        Text => "text",
+        Wysiwyg => "wysiwyg",
+        Int => "int",
+        Float => "float",
+        Currency => "currency",
+        CurrencyConverted => "currencyConverted",
+        Bool => "bool",
+        Date => "date",
+        Datetime => "datetime",
+        DatetimeOptional => "datetimeOptional",
+        Enum => "enum",
+        MultiEnum => "multiEnum",
+        Array => "array",
+        Checklist => "checklist",
+        Link => "link",
+        LinkOne => "linkOne",
+        LinkParent => "linkParent",
+        LinkMultiple => "linkMultiple",
+        File => "file",
+        Image => "image",
+        AttachmentMultiple => "attachmentMultiple",
+        Email => "email",
+        Phone => "phone",
+        Url => "url",
+        Address => "address",
+        PersonName => "personName",
+        AutoIncrement => "autoincrement",
+        Number => "number",
+        JsonObject => "jsonObject",
+        JsonArray => "jsonArray",
+        Foreign => "foreign",
+        Password => "password",
+        Barcode => "barcode",
+        ColorPicker => "colorpicker",
+    }
+}
+
+string_enum! {
+    /// The type of a relationship
+    LinkType {
+        BelongsTo => "belongsTo",
+        BelongsToParent => "belongsToParent",
+        HasOne => "hasOne",
+        HasMany => "hasMany",
+        HasChildren => "hasChildren",
+    }
+}
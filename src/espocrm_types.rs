@@ -1,16 +1,27 @@
+use crate::espocrm_date::{EspoDate, EspoDateTime};
+use serde::Deserialize;
 use std::fmt;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[allow(unused)]
 pub enum Value {
     String(Option<String>),
     Array(Option<Vec<Value>>),
     Integer(Option<i64>),
     Boolean(Option<bool>),
+    /// A decimal number, e.g. the amount of a currency field
+    Float(Option<f64>),
+    /// An explicit null, sent as an empty value in query strings
+    Null,
+    /// A date, serialized as `YYYY-MM-DD`
+    Date(Option<EspoDate>),
+    /// A date and time in UTC, serialized as `YYYY-MM-DD HH:MM:SS`
+    DateTime(Option<EspoDateTime>),
 }
 
 #[allow(unused)]
 impl Value {
+    #[allow(clippy::should_implement_trait)]
     pub fn eq(&self, b: &Value) -> bool {
         std::mem::discriminant(self) == std::mem::discriminant(b)
     }
@@ -34,9 +45,179 @@ impl Value {
     pub fn bool(v: bool) -> Self {
         Value::Boolean(Some(v))
     }
+
+    pub fn float(v: f64) -> Self {
+        Value::Float(Some(v))
+    }
+
+    /// Create a date value
+    pub fn date<D: Into<EspoDate>>(v: D) -> Self {
+        Value::Date(Some(v.into()))
+    }
+
+    /// Create a date and time value
+    pub fn datetime<D: Into<EspoDateTime>>(v: D) -> Self {
+        Value::DateTime(Some(v.into()))
+    }
+}
+
+/// A typed EspoCRM entity, such as a Contact or Account.
+///
+/// Implementing this trait lets the typed helpers on [EspoApiClient](crate::EspoApiClient), such as [get](crate::EspoApiClient::get),
+/// know which entity type to request. With the `derive` feature enabled, this trait can be derived:
+/// ```ignore
+/// use espocrm_rs::Entity;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Entity, Serialize, Deserialize)]
+/// #[espo(entity = "Contact")]
+/// #[serde(rename_all = "camelCase")]
+/// struct Contact {
+///     id: String,
+///     first_name: Option<String>,
+///     #[serde(rename = "cCustomerNumber")]
+///     customer_number: Option<String>,
+/// }
+/// ```
+pub trait Entity {
+    /// The name of the entity type in EspoCRM. E.g. "Contact"
+    const ENTITY_TYPE: &'static str;
+    /// The names of the attributes in EspoCRM this type consists of
+    const FIELDS: &'static [&'static str];
+
+    /// The attributes of this entity, formatted for use with [Params::set_select]
+    fn select() -> String {
+        Self::FIELDS.join(",")
+    }
+}
+
+/// A page of records, as returned by EspoCRM's list endpoints
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize)]
+pub struct ListResponse<T> {
+    /// The total number of records matching the query.
+    /// EspoCRM may return a negative number if counting is disabled for the entity
+    pub total: i64,
+    /// The records in this page
+    pub list: Vec<T>,
 }
 
+/// The maximum number of records EspoCRM returns in a single page
+pub const MAX_PAGE_SIZE: i64 = 200;
+
+/// How records are ordered while paginating with [list_stream](crate::EspoApiClient::list_stream)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StableOrder {
+    /// Keep the order configured in the [Params].
+    /// Records may be skipped or returned twice if data changes while paginating
+    None,
+    /// Order by `id`, and fetch each following page with `id > <last id>` instead of an offset.
+    /// Records are never skipped or returned twice, even if data changes while paginating
+    Id,
+    /// Order by `createdAt`, oldest first.
+    /// Records created while paginating are returned at the end, but deleting records may cause others to be skipped
+    CreatedAt,
+}
+
+/// Options for [list_stream](crate::EspoApiClient::list_stream)
 #[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PaginationOptions {
+    /// The number of records to fetch per request, between 1 and [MAX_PAGE_SIZE]. Values outside that range are clamped
+    pub page_size: i64,
+    pub stable_order: StableOrder,
+}
+
+impl Default for PaginationOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PaginationOptions {
+    pub fn new() -> Self {
+        Self {
+            page_size: MAX_PAGE_SIZE,
+            stable_order: StableOrder::None,
+        }
+    }
+
+    /// Set the number of records to fetch per request.
+    /// The value is clamped between 1 and [MAX_PAGE_SIZE]
+    pub fn set_page_size(&mut self, page_size: i64) -> &mut Self {
+        self.page_size = page_size.clamp(1, MAX_PAGE_SIZE);
+        self
+    }
+
+    pub fn set_stable_order(&mut self, stable_order: StableOrder) -> &mut Self {
+        self.stable_order = stable_order;
+        self
+    }
+
+    pub fn build(&self) -> Self {
+        self.clone()
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Self {
+        Value::str(v)
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Self {
+        Value::string(v)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(v: i64) -> Self {
+        Value::int(v)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(v: i32) -> Self {
+        Value::int(v.into())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(v: bool) -> Self {
+        Value::bool(v)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(v: Vec<Value>) -> Self {
+        Value::array(v)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Self {
+        Value::float(v)
+    }
+}
+
+impl From<EspoDate> for Value {
+    fn from(v: EspoDate) -> Self {
+        Value::date(v)
+    }
+}
+
+impl From<EspoDateTime> for Value {
+    fn from(v: EspoDateTime) -> Self {
+        Value::datetime(v)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 #[allow(unused)]
 pub struct Params {
     pub offset: Option<i64>,
@@ -44,7 +225,7 @@ pub struct Params {
     pub select: Option<String>,
     pub r#where: Option<Vec<Where>>,
     pub primary_filter: Option<String>,
-    pub bool_filter_list: Option<Vec<String>>,
+    pub bool_filter_list: Option<Vec<BoolFilter>>,
     pub order: Option<Order>,
     pub order_by: Option<String>,
 }
@@ -85,8 +266,9 @@ impl Params {
         self
     }
 
-    pub fn set_where(&mut self, r#where: Vec<Where>) -> &mut Self {
-        self.r#where = Some(r#where);
+    /// Set the where clauses. See [filter](crate::filter) for a convenient way to build them
+    pub fn set_where<I: IntoIterator<Item = Where>>(&mut self, r#where: I) -> &mut Self {
+        self.r#where = Some(r#where.into_iter().collect());
         self
     }
 
@@ -95,8 +277,13 @@ impl Params {
         self
     }
 
-    pub fn set_bool_filter_list(&mut self, bool_filter_list: Vec<String>) -> &mut Self {
-        self.bool_filter_list = Some(bool_filter_list);
+    /// Set the bool filters to apply. Accepts [BoolFilter]s, as well as the names of filters as strings
+    pub fn set_bool_filter_list<I, B>(&mut self, bool_filter_list: I) -> &mut Self
+    where
+        I: IntoIterator<Item = B>,
+        B: Into<BoolFilter>,
+    {
+        self.bool_filter_list = Some(bool_filter_list.into_iter().map(Into::into).collect());
         self
     }
 
@@ -115,6 +302,51 @@ impl Params {
     }
 }
 
+/// A bool filter, as used in the `boolFilterList` of a list request
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum BoolFilter {
+    /// Records assigned to the current user
+    OnlyMy,
+    /// Records assigned to one of the current user's teams
+    OnlyMyTeam,
+    /// Records followed by the current user
+    Followed,
+    /// Records shared with the current user
+    Shared,
+    /// A bool filter not built into EspoCRM, e.g. one defined by an extension or custom code
+    Custom(String),
+}
+
+impl From<&str> for BoolFilter {
+    fn from(value: &str) -> Self {
+        match value {
+            "onlyMy" => Self::OnlyMy,
+            "onlyMyTeam" => Self::OnlyMyTeam,
+            "followed" => Self::Followed,
+            "shared" => Self::Shared,
+            _ => Self::Custom(value.to_string()),
+        }
+    }
+}
+
+impl From<String> for BoolFilter {
+    fn from(value: String) -> Self {
+        Self::from(value.as_str())
+    }
+}
+
+impl fmt::Display for BoolFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OnlyMy => write!(f, "onlyMy"),
+            Self::OnlyMyTeam => write!(f, "onlyMyTeam"),
+            Self::Followed => write!(f, "followed"),
+            Self::Shared => write!(f, "shared"),
+            Self::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 #[allow(unused)]
 pub enum Order {
@@ -128,23 +360,60 @@ impl fmt::Display for Order {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 #[allow(unused)]
-pub struct Where {
-    pub r#type: FilterType,
-    pub attribute: String,
-    pub value: Option<Value>,
+pub enum Where {
+    /// A filter on a single attribute
+    Clause {
+        r#type: FilterType,
+        attribute: String,
+        value: Option<Value>,
+    },
+    /// A group of nested clauses, created with [Where::or], [Where::and] and [Where::not]
+    Group {
+        r#type: GroupType,
+        clauses: Vec<Where>,
+    },
 }
 
 #[allow(unused)]
 impl Where {
     pub fn new(filter_type: FilterType, attribute: &str, value: Option<Value>) -> Self {
-        Where {
+        Where::Clause {
             r#type: filter_type,
             attribute: attribute.to_string(),
             value,
         }
     }
+
+    /// A group of clauses of which at least one must match
+    pub fn or(clauses: Vec<Where>) -> Self {
+        Where::Group { r#type: GroupType::Or, clauses }
+    }
+
+    /// A group of clauses which must all match
+    pub fn and(clauses: Vec<Where>) -> Self {
+        Where::Group { r#type: GroupType::And, clauses }
+    }
+
+    /// A group of clauses which must not match
+    pub fn not(clauses: Vec<Where>) -> Self {
+        Where::Group { r#type: GroupType::Not, clauses }
+    }
+}
+
+/// How the clauses of a [Where::Group] are combined
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GroupType {
+    Or,
+    And,
+    Not,
+}
+
+impl fmt::Display for GroupType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -172,6 +441,7 @@ pub enum FilterType {
     EndsWith,
     Like,
     NotLike,
+    /// Only valid as the type of a [Where::Group], use [Where::or]
     Or,
     AndToday,
     Past,
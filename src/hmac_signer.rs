@@ -0,0 +1,117 @@
+use crate::secret::Secret;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::fmt;
+use zeroize::Zeroizing;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Name of the header carrying the HMAC signature of a request
+pub const HMAC_HEADER: &str = "X-Hmac-Authorization";
+
+/// Computes and verifies the `X-Hmac-Authorization` header EspoCRM uses for HMAC authentication.
+///
+/// The signature is an HMAC-SHA256 over `"{METHOD} /{path}"` with the secret key, where `path` is the part of the URL after `/api/v1/`.
+/// The header is `base64("{api key}:{signature}")`, the format of EspoCRM's own API client.
+///
+/// **The query string is not signed.** EspoCRM only signs the path, so the `where` clauses and other [Params](crate::Params)
+/// of a GET request are not protected by the signature. Paths passed to the signer may include a query string, which is ignored.
+///
+/// The signer can be used on its own, e.g. to verify or re-sign requests in a gateway:
+/// ```rust
+/// use espocrm_rs::HmacSigner;
+///
+/// let signer = HmacSigner::new("api-key", "secret");
+/// let header = signer.sign("GET", "Contact?maxSize=10").unwrap();
+///
+/// assert!(signer.verify("GET", "Contact", &header).is_ok());
+/// assert!(signer.verify("DELETE", "Contact", &header).is_err());
+/// ```
+#[derive(Clone, Debug)]
+pub struct HmacSigner {
+    api_key: Secret,
+    secret_key: Secret,
+}
+
+/// Errors returned by [HmacSigner]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum HmacError {
+    /// The secret key can't be used as an HMAC key
+    InvalidKey,
+    /// The header is not valid base64, or does not contain an API key and signature
+    MalformedHeader,
+    /// The header was signed for another API key
+    ApiKeyMismatch,
+    /// The signature does not match the method and path
+    InvalidSignature,
+}
+
+impl fmt::Display for HmacError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidKey => write!(f, "The secret key can't be used as an HMAC key"),
+            Self::MalformedHeader => write!(f, "The {HMAC_HEADER} header is malformed"),
+            Self::ApiKeyMismatch => write!(f, "The {HMAC_HEADER} header was signed for another API key"),
+            Self::InvalidSignature => write!(f, "The signature in the {HMAC_HEADER} header is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for HmacError {}
+
+impl HmacSigner {
+    pub fn new<K: AsRef<str>, S: AsRef<str>>(api_key: K, secret_key: S) -> Self {
+        Self {
+            api_key: Secret::new(api_key.as_ref()),
+            secret_key: Secret::new(secret_key.as_ref()),
+        }
+    }
+
+    /// The value of the `X-Hmac-Authorization` header for a request.
+    ///
+    /// * method: The HTTP method, e.g. `GET`
+    /// * path: The part of the URL after `/api/v1/`, e.g. `Contact/1`. A leading `/` and the query string are ignored
+    ///
+    /// # Errors
+    ///
+    /// If the secret key can't be used as an HMAC key
+    pub fn sign(&self, method: &str, path: &str) -> Result<String, HmacError> {
+        let mac = self.mac(method, path)?.finalize().into_bytes();
+
+        let mut credentials = Zeroizing::new(Vec::with_capacity(self.api_key.expose().len() + 1 + mac.len()));
+        credentials.extend_from_slice(self.api_key.expose().as_bytes());
+        credentials.push(b':');
+        credentials.extend_from_slice(&mac);
+
+        Ok(base64::encode(&*credentials))
+    }
+
+    /// Check that `header` is a valid `X-Hmac-Authorization` header for a request, signed by this signer's keys.
+    /// The signature is compared in constant time.
+    ///
+    /// # Errors
+    ///
+    /// If the header is malformed, was signed for another API key, or its signature does not match `method` and `path`
+    pub fn verify(&self, method: &str, path: &str, header: &str) -> Result<(), HmacError> {
+        let credentials = Zeroizing::new(base64::decode(header.trim()).map_err(|_| HmacError::MalformedHeader)?);
+        let separator = credentials.iter().position(|x| *x == b':').ok_or(HmacError::MalformedHeader)?;
+        let (api_key, signature) = (&credentials[..separator], &credentials[separator + 1..]);
+
+        if api_key != self.api_key.expose().as_bytes() {
+            return Err(HmacError::ApiKeyMismatch);
+        }
+
+        self.mac(method, path)?
+            .verify_slice(signature)
+            .map_err(|_| HmacError::InvalidSignature)
+    }
+
+    fn mac(&self, method: &str, path: &str) -> Result<HmacSha256, HmacError> {
+        let path = path.split('?').next().unwrap_or_default().trim_start_matches('/');
+        let message = format!("{} /{}", method.to_ascii_uppercase(), path);
+
+        let mut mac = HmacSha256::new_from_slice(self.secret_key.expose().as_bytes()).map_err(|_| HmacError::InvalidKey)?;
+        mac.update(message.as_bytes());
+        Ok(mac)
+    }
+}
@@ -4,40 +4,78 @@
 //! You can find this client [here](https://docs.espocrm.com/development/api-client-php/).
 //!
 //! ## Getting started
-//! To get started you'll have to provide the URL where EspoCRM is located at. You will also have to set the way you want to authenticate with EspoCRM.
-//! This can be done in one of the following ways:
-//! - Username+Password
-//! - API Key
-//! - HMAC (Recommended)
+//! To get started you'll have to provide the URL where EspoCRM is located at. You will also have to set the way you want to authenticate with EspoCRM,
+//! with an [Auth]. This can be done in one of the following ways:
+//! - Username+Password, with [Auth::basic], or [Auth::session] to log in once and use a token for the following requests
+//! - Username+Password with two-factor authentication, with [Auth::session_2fa]
+//! - API Key, with [Auth::api_key]
+//! - HMAC (Recommended), with [Auth::hmac]
+//! - A header required by a reverse proxy, with [Auth::header]
+//! - Your own [AuthProvider], with [Auth::custom]
 //!
 //! The following example creates an EspoApiClient with HMAC authorization
 //! ```rust
-//! use espocrm_rs::EspoApiClient;
+//! use espocrm_rs::{Auth, EspoApiClient};
 //!
 //! let client = EspoApiClient::new("https://espocrm.example.com")
-//!     .set_api_key("Your API Key here")
-//!     .set_secret_key("Your API Secret")
+//!     .set_auth(Auth::hmac("Your API Key here", "Your API Secret"))
 //!     .build();
 //! ```
 //!
 //! The following example creates an EspoApiClient with API Key authorization
 //! ```rust
-//! use espocrm_rs::EspoApiClient;
+//! use espocrm_rs::{Auth, EspoApiClient};
 //! let client = EspoApiClient::new("https://espocrm.example.com")
-//!     .set_api_key("Your API Key here")
+//!     .set_auth(Auth::api_key("Your API Key here"))
 //!     .build();
 //! ```
 //!
 //! The following example creates an EspoApiClient with Username+Password authorization.
 //! **This is highly discouraged!**
 //! ```rust
-//! use espocrm_rs::EspoApiClient;
+//! use espocrm_rs::{Auth, EspoApiClient};
+//! let client = EspoApiClient::new("https://espocrm.example.com")
+//!     .set_auth(Auth::basic("Your Username here", "Your Password here"))
+//!     .build();
+//! ```
+//!
+//! Requests have no timeout by default. Timeouts can be set on the client, and overridden for a single request with [EspoApiClient::with_timeout]
+//! ```rust
+//! use espocrm_rs::{Auth, EspoApiClient};
+//! use std::time::Duration;
+//!
+//! let client = EspoApiClient::new("https://espocrm.example.com")
+//!     .set_auth(Auth::api_key("Your API Key here"))
+//!     .set_connect_timeout(Duration::from_secs(5))
+//!     .set_read_timeout(Duration::from_secs(30))
+//!     .set_timeout(Duration::from_secs(60))
+//!     .build();
+//! ```
+//!
+//! HTTPS is supported with rustls by default. To use the platform's TLS library instead, disable the default features and enable `native-tls`.
+//! Servers with a certificate from an internal CA can be trusted with [EspoApiClient::add_root_certificate].
+//!
+//! The client keeps a pool of connections, so it should be created once and reused (or cloned) for all requests.
+//! If you need proxies, you can provide your own [reqwest::Client]
+//! ```rust
+//! use espocrm_rs::{Auth, EspoApiClient};
+//!
+//! let http_client = reqwest::Client::builder()
+//!     .pool_max_idle_per_host(4)
+//!     .build()
+//!     .unwrap();
+//!
 //! let client = EspoApiClient::new("https://espocrm.example.com")
-//!     .set_username("Your Username here")
-//!     .set_password("Your Password here")
+//!     .set_auth(Auth::api_key("Your API Key here"))
+//!     .set_http_client(http_client)
 //!     .build();
 //! ```
 //!
+//! To add headers, audit logs or metrics to every request, implement [Middleware] and add it with [EspoApiClient::add_middleware].
+//! Requests are sent by a [Transport], which is the reqwest client by default. Tests can use a [MemoryTransport] to record requests and return canned responses without a server.
+//!
+//! For synchronous programs, the `blocking` feature provides [blocking::EspoBlockingClient], which has the same methods but does not require an async runtime.
+//!
 //! # Making a GET request
 //! To make a request, you need to know a couple things:
 //! - The request method to use
@@ -46,17 +84,17 @@
 //!
 //! Most of these things are laid out pretty well in the EspoCRM API documentation [here](https://docs.espocrm.com/development/api/)
 //! ```rust
-//! use espocrm_rs::{EspoApiClient, Params, Where, FilterType, Value, NoGeneric, Method};
+//! use espocrm_rs::{Auth, EspoApiClient, Params, Where, FilterType, Value, NoGeneric, Method};
 //!
 //! let params = Params::default()
 //!     .set_offset(0)
 //!     .set_where(vec![
-//!         Where {
+//!         Where::Clause {
 //!             r#type: FilterType::IsTrue,
 //!             attribute: "exampleField".to_string(),
 //!             value: None
 //!         },
-//!         Where {
+//!         Where::Clause {
 //!             r#type: FilterType::ArrayAnyOf,
 //!             attribute: "exampleField2".to_string(),
 //!             value: Some(Value::array(vec![
@@ -69,29 +107,144 @@
 //!     .build();
 //!
 //! let client = EspoApiClient::new("https://espocrm.example.com")
-//!     .set_secret_key("Your Secret Key")
-//!     .set_api_key("Your api key")
+//!     .set_auth(Auth::hmac("Your api key", "Your Secret Key"))
 //!     .build();
 //!
 //! let result = client.request::<NoGeneric, &str>(Method::Get, "Contact", Some(params), None);
 //! ```
 //!
+//! Where clauses can also be built with [filter], which only accepts the values a filter type needs.
+//! Clauses can be grouped with [or], [and] and [not], and groups can be nested:
+//! ```rust
+//! use espocrm_rs::{filter, or, Params};
+//!
+//! // status is 'New' OR assigned to user '1', and created in the last 7 days
+//! let params = Params::default()
+//!     .set_where([
+//!         or([filter("status").eq("New"), filter("assignedUserId").eq("1")]),
+//!         filter("createdAt").last_x_days(7),
+//!     ])
+//!     .build();
+//! ```
+//!
+//! Dates are passed as [EspoDate] and [EspoDateTime], which are formatted the way EspoCRM expects them.
+//! With the `chrono` or `time` feature enabled, their date types can be converted into these with [TryFrom]:
+//! ```rust
+//! use espocrm_rs::{filter, EspoDate, Params};
+//!
+//! let params = Params::default()
+//!     .set_where([
+//!         filter("closeDate").gte(EspoDate::new(2024, 1, 31).unwrap()),
+//!         filter("amount").gt(99.5),
+//!     ])
+//!     .build();
+//! ```
+//!
+//! # Fetching typed records
+//! Instead of working with the raw response, you can let the client deserialize records for you.
+//! To do so, your type has to implement [Entity], which tells the client what the entity type is called in EspoCRM.
+//! With the `derive` feature enabled, [Entity] can be derived. Its fields are the attributes serde deserializes the struct from,
+//! so use `#[serde(rename_all = "camelCase")]` to map snake_case fields to EspoCRM's camelCase attributes.
+//!
+//! List endpoints return a [ListResponse], containing the total number of matching records and the records in the requested page.
+//! ```rust
+//! use espocrm_rs::{Entity, EspoApiClient, ListResponse, Params};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug)]
+//! struct Contact {
+//!     id: String,
+//!     #[serde(rename = "firstName")]
+//!     first_name: Option<String>,
+//! }
+//!
+//! impl Entity for Contact {
+//!     const ENTITY_TYPE: &'static str = "Contact";
+//!     const FIELDS: &'static [&'static str] = &["id", "firstName"];
+//! }
+//!
+//! async fn fetch_contacts(client: &EspoApiClient) {
+//!     let contact: Contact = client.get("some-id").await.unwrap();
+//!
+//!     let params = Params::new().set_max_size(20).set_select(&Contact::select()).build();
+//!     let contacts: ListResponse<Contact> = client.list(params).await.unwrap();
+//!     println!("Got {} of {} contacts", contacts.list.len(), contacts.total);
+//! }
+//! ```
+//!
+//! # Iterating over all records
+//! [list_stream](EspoApiClient::list_stream) returns a [Stream](futures::Stream) over all records matching a query, fetching pages as they are needed.
+//! ```rust
+//! use espocrm_rs::{Entity, EspoApiClient, PaginationOptions, Params, StableOrder};
+//! use futures::TryStreamExt;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Debug)]
+//! struct Account {
+//!     id: String,
+//!     name: String,
+//! }
+//!
+//! impl Entity for Account {
+//!     const ENTITY_TYPE: &'static str = "Account";
+//!     const FIELDS: &'static [&'static str] = &["id", "name"];
+//! }
+//!
+//! async fn export_accounts(client: &EspoApiClient) {
+//!     let options = PaginationOptions::new()
+//!         .set_page_size(200)
+//!         .set_stable_order(StableOrder::Id)
+//!         .build();
+//!
+//!     let accounts: Vec<Account> = client
+//!         .list_stream(Params::default(), options)
+//!         .try_collect()
+//!         .await
+//!         .unwrap();
+//! }
+//! ```
+//!
+//! # Metadata
+//! [metadata](EspoApiClient::metadata) fetches the definitions of all entities, fields and links configured in EspoCRM.
+//! These can be used to validate a payload before sending it.
+//! ```rust
+//! use espocrm_rs::{EspoApiClient, ValidationMode};
+//!
+//! async fn validate_contact(client: &EspoApiClient, contact: serde_json::Value) {
+//!     let metadata = client.metadata().await.unwrap();
+//!     let defs = metadata.entity("Contact").unwrap();
+//!
+//!     if let Err(errors) = defs.validate(&contact, ValidationMode::Create) {
+//!         for error in errors {
+//!             println!("{error}");
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! # Generating entities from metadata
+//! Rather than writing the structs for your entities by hand, you can generate them from a snapshot of EspoCRM's metadata (`GET /api/v1/Metadata`).
+//! This can be done from a build script with [generate_entities_from_json], or with the `espocrm-codegen` binary:
+//! ```text
+//! espocrm-codegen metadata.json --entity Contact --entity Account --output src/entities.rs
+//! ```
+//! The generated code requires `serde` (with the `derive` feature) and `serde_json` as dependencies.
+//!
 //! # Making a POST, PUT or DELETE request
 //! These are all similar in working. They'll serialize your data into json using Serde's serialize trait
 //!
 //! ```rust
-//! use espocrm_rs::{EspoApiClient, Method};
+//! use espocrm_rs::{Auth, EspoApiClient, Method};
 //! use serde::Serialize;
 //!
-//! #[derive(Serialize, Clone)]
+//! #[derive(Serialize, Clone, Debug)]
 //! struct MyData {
 //!     some_value:         String,
 //!     some_other_value:   i64
 //! }
 //!
 //! let client = EspoApiClient::new("https://espocrm.example.com")
-//!     .set_secret_key("Your Secret Key")
-//!     .set_api_key("Your api key")
+//!     .set_auth(Auth::hmac("Your api key", "Your Secret Key"))
 //!     .build();
 //!
 //! let data = MyData {
@@ -102,28 +255,159 @@
 //! let result = client.request(Method::Post, "Contact", None, Some(data));
 //!```
 //!
+//! For the common operations on a single record there are dedicated methods, so you don't need to build the action yourself:
+//! [create](EspoApiClient::create), [read](EspoApiClient::read), [update](EspoApiClient::update) and [delete](EspoApiClient::delete).
+//! ```rust
+//! use espocrm_rs::EspoApiClient;
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize, Clone, Debug)]
+//! struct ContactUpdate {
+//!     #[serde(rename = "lastName")]
+//!     last_name: String,
+//! }
+//!
+//! async fn rename_contact(client: &EspoApiClient, id: &str) {
+//!     let update = ContactUpdate { last_name: "Doe".to_string() };
+//!     client.update("Contact", id, update).await.unwrap();
+//! }
+//! ```
+//!
+//! # Handling errors
+//! All requests return an [EspoError] if the request could not be sent, or if EspoCRM responds with a non-success status code.
+//! The value of the `X-Status-Reason` header EspoCRM sends along with failed requests is available through [EspoError::status_reason].
+//! ```rust
+//! use espocrm_rs::{EspoApiClient, EspoError};
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize, Clone, Debug)]
+//! struct Contact {
+//!     #[serde(rename = "firstName")]
+//!     first_name: String,
+//! }
+//!
+//! async fn create_contact(client: &EspoApiClient) {
+//!     let contact = Contact { first_name: "John".to_string() };
+//!     match client.create("Contact", contact).await {
+//!         Ok(response) => println!("Created: {}", response.status()),
+//!         Err(EspoError::Duplicate { records, .. }) => println!("Found {} duplicates", records.len()),
+//!         Err(e) => println!("Failed: {e}, reason: {:?}", e.status_reason()),
+//!     }
+//! }
+//! ```
+//!
 
 extern crate core;
+// Allows the derive macros to refer to `::espocrm_rs` from within this crate
+extern crate self as espocrm_rs;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod auth;
+mod call;
+mod codegen;
 mod espocrm_api_client;
+mod espocrm_date;
+mod espocrm_error;
+mod espocrm_filter;
+mod espocrm_metadata;
 mod espocrm_types;
+mod hmac_signer;
+mod middleware;
+mod pagination;
+mod rate_limit;
+mod retry;
+mod secret;
 mod serializer;
+mod session;
 mod tracing_if;
+mod transport;
 
+pub use auth::*;
+pub use codegen::*;
 pub use espocrm_api_client::*;
+pub use espocrm_date::*;
+pub use espocrm_error::*;
+pub use espocrm_filter::*;
+pub use espocrm_metadata::*;
 pub use espocrm_types::*;
+pub use hmac_signer::*;
+pub use middleware::{AfterReceive, EspoRequest, Middleware, ResponseHead};
+pub use retry::*;
+pub use session::{SessionUser, TwoFactorCode};
+pub use transport::{MemoryTransport, Transport};
+
+#[cfg(feature = "derive")]
+pub use espocrm_rs_derive::Entity;
+
+#[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+pub use reqwest::Certificate;
 
 #[cfg(test)]
 mod tests {
-    use crate::espocrm_api_client::EspoApiClient;
-    use crate::espocrm_types::{FilterType, Order, Params, Value, Where};
+    use crate::auth::{Auth, AuthKind, AuthProvider};
+    use crate::call::Call;
+    use crate::codegen::{generate_entities_from_json, CodegenOptions};
+    use crate::espocrm_api_client::{EspoApiClient, Method};
+    use crate::espocrm_date::{EspoDate, EspoDateTime};
+    use crate::espocrm_error::{EspoError, SerializeError};
+    use crate::espocrm_filter::{and, filter, not, or};
+    use crate::espocrm_metadata::{FieldErrorKind, FieldType, LinkType, Metadata, ValidationMode};
+    use crate::espocrm_types::{
+        BoolFilter, Entity, FilterType, GroupType, ListResponse, Order, PaginationOptions, Params, StableOrder, Value, Where,
+    };
+    use crate::hmac_signer::{HmacError, HmacSigner, HMAC_HEADER};
+    use crate::middleware::{AfterReceive, EspoRequest, Middleware, ResponseHead};
+    use crate::pagination::PageState;
+    use crate::rate_limit::RateLimiter;
+    use crate::retry::RetryPolicy;
+    use crate::secret::Secret;
     use crate::serializer::serialize;
+    use crate::session::TwoFactorCode;
+    use crate::transport::MemoryTransport;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use reqwest::StatusCode;
     use std::collections::HashSet;
     use std::hash::Hash;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
 
     const URL: &str = "foo";
 
-    fn assert_eq_unsorted_vec<T: Eq + Hash>(a: &Vec<T>, b: &Vec<T>) -> bool {
+    /// Serve one canned HTTP response per connection on a local port, in order.
+    /// Returns the URL of the server and the request lines and headers it received
+    fn serve(responses: Vec<&'static str>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let received = requests.clone();
+        std::thread::spawn(move || {
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+
+                received.lock().unwrap().push(request);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn assert_eq_unsorted_vec<T: Eq + Hash>(a: &[T], b: &[T]) -> bool {
         let a: HashSet<_> = a.iter().collect();
         let b: HashSet<_> = b.iter().collect();
 
@@ -137,34 +421,39 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn username() {
         let client = EspoApiClient::new(URL).set_username("bar").build();
 
-        assert_eq!(Some("bar".to_string()), client.username);
+        assert_eq!(Some("bar".to_string()), client.credentials.username);
     }
 
     #[test]
+    #[allow(deprecated)]
     fn password() {
         let client = EspoApiClient::new(URL).set_password("bar").build();
 
-        assert_eq!(Some("bar".to_string()), client.password);
+        assert_eq!(Some("bar"), client.credentials.password.as_ref().map(Secret::expose));
     }
 
     #[test]
+    #[allow(deprecated)]
     fn api_key() {
         let client = EspoApiClient::new(URL).set_api_key("bar").build();
 
-        assert_eq!(Some("bar".to_string()), client.api_key);
+        assert_eq!(Some("bar"), client.credentials.api_key.as_ref().map(Secret::expose));
     }
 
     #[test]
+    #[allow(deprecated)]
     fn secret_key() {
         let client = EspoApiClient::new(URL).set_secret_key("bar").build();
 
-        assert_eq!(Some("bar".to_string()), client.secret_key);
+        assert_eq!(Some("bar"), client.credentials.secret_key.as_ref().map(Secret::expose));
     }
 
     #[test]
+    #[allow(deprecated)]
     fn full() {
         let client = EspoApiClient::new(URL)
             .set_username("username")
@@ -173,10 +462,11 @@ mod tests {
             .set_secret_key("secret_key")
             .build();
 
-        assert_eq!(Some("username".to_string()), client.username);
-        assert_eq!(Some("password".to_string()), client.password);
-        assert_eq!(Some("api_key".to_string()), client.api_key);
-        assert_eq!(Some("secret_key".to_string()), client.secret_key);
+        assert_eq!(Some("username".to_string()), client.credentials.username);
+        assert_eq!(Some("password"), client.credentials.password.as_ref().map(Secret::expose));
+        assert_eq!(Some("api_key"), client.credentials.api_key.as_ref().map(Secret::expose));
+        assert_eq!(Some("secret_key"), client.credentials.secret_key.as_ref().map(Secret::expose));
+        assert!(matches!(client.auth.0, AuthKind::Basic { .. }));
         assert_eq!("foo".to_string(), client.url);
     }
 
@@ -187,10 +477,331 @@ mod tests {
         assert_eq!("bar".to_string(), client.url)
     }
 
+    #[test]
+    fn method_patch() {
+        assert_eq!(reqwest::Method::PATCH, reqwest::Method::from(Method::Patch));
+    }
+
+    #[test]
+    fn calls() {
+        let params = Params::new().set_max_size(1).build();
+        let data = serde_json::json!({"name": "Alice"});
+
+        // A GET request only sends the query, other requests only the body
+        let call = Call::request(Method::Get, "Contact", Some(params.clone()), Some(&data)).unwrap();
+        assert_eq!((Some("maxSize=1"), None), (call.query.as_deref(), call.body.as_deref()));
+        let call = Call::request(Method::Post, "Contact", Some(params), Some(&data)).unwrap();
+        assert_eq!((None, Some(br#"{"name":"Alice"}"#.as_slice())), (call.query.as_deref(), call.body.as_deref()));
+
+        let call = Call::update_put("Contact", "1", &data).unwrap();
+        assert_eq!((reqwest::Method::PUT, "Contact/1"), (call.method, call.action.as_str()));
+
+        let client = EspoApiClient::new(URL).set_timeout(Duration::from_secs(5)).build();
+        let request = client.new_request(Call::create_allow_duplicates("Contact", &data).unwrap()).unwrap();
+        assert_eq!("foo/api/v1/Contact", request.url);
+        assert_eq!("true", request.headers["X-Skip-Duplicate-Check"]);
+        assert_eq!("application/json", request.headers["Content-Type"]);
+        assert_eq!(Some(Duration::from_secs(5)), request.timeout);
+
+        let request = client.new_request(Call::metadata_key("entityDefs.Contact")).unwrap();
+        assert_eq!("foo/api/v1/Metadata/action/get?key=entityDefs.Contact", request.url);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn auth_replaces_credentials() {
+        let client = EspoApiClient::new(URL)
+            .set_api_key("api_key")
+            .set_secret_key("secret_key")
+            .set_auth(Auth::header("X-Proxy-Auth", "token"))
+            .build();
+        assert!(client.credentials.api_key.is_none());
+        assert!(matches!(&client.auth.0, AuthKind::Header { name, .. } if name == "X-Proxy-Auth"));
+
+        // A deprecated setter starts over from the credentials set with the other deprecated setters
+        let mut client = EspoApiClient::new(URL).set_auth(Auth::basic("user", "pass")).set_api_key("api_key").build();
+        assert!(matches!(client.auth.0, AuthKind::ApiKey(_)));
+
+        client.set_auth(Auth::header("X-Proxy-Auth", "token"));
+        let mut request = client.new_request(Call::new(reqwest::Method::GET, "Contact")).unwrap();
+        client.auth.before_send(&mut request).unwrap();
+        assert_eq!("token", request.headers["X-Proxy-Auth"]);
+        assert!(request.headers["X-Proxy-Auth"].is_sensitive());
+        assert!(!request.headers.contains_key("X-Api-Key"));
+    }
+
+    #[tokio::test]
+    async fn custom_auth_provider() {
+        #[derive(Debug)]
+        struct Provider(bool);
+
+        impl AuthProvider for Provider {
+            fn headers(&self, method: &str, action: &str) -> Result<Vec<(String, String)>, Box<dyn std::error::Error + Send + Sync>> {
+                if !self.0 {
+                    return Err("secrets file not found".into());
+                }
+
+                Ok(vec![
+                    ("X-Api-Key".to_string(), "key".to_string()),
+                    ("X-Proxy-Token".to_string(), format!("{method} {action}")),
+                ])
+            }
+        }
+
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}";
+        let (url, requests) = serve(vec![OK]);
+
+        let client = EspoApiClient::new(&url).set_auth(Auth::custom(Provider(true))).build();
+        client.read("Contact", "1").await.unwrap();
+
+        let received = requests.lock().unwrap().clone();
+        assert!(received[0].contains("x-api-key: key"));
+        assert!(received[0].contains("x-proxy-token: GET Contact/1"));
+
+        // The server has stopped, so the request would fail with a transport error if it were sent
+        let client = EspoApiClient::new(&url).set_auth(Auth::custom(Provider(false))).build();
+        let error = client.read("Contact", "1").await.unwrap_err();
+        assert!(matches!(error, EspoError::Auth(_)));
+        assert_eq!("Failed to provide credentials: secrets file not found", error.to_string());
+    }
+
+    #[tokio::test]
+    async fn session_auth() {
+        const LOGIN_1: &str = "HTTP/1.1 200 OK\r\nContent-Length: 99\r\nConnection: close\r\n\r\n{\"token\":\"tok1\",\"user\":{\"id\":\"1\",\"userName\":\"admin\",\"name\":\"Admin\",\"type\":\"admin\",\"isActive\":true}}";
+        const LOGIN_2: &str = "HTTP/1.1 200 OK\r\nContent-Length: 99\r\nConnection: close\r\n\r\n{\"token\":\"tok2\",\"user\":{\"id\":\"1\",\"userName\":\"admin\",\"name\":\"Admin\",\"type\":\"admin\",\"isActive\":true}}";
+        const UNAUTHORIZED: &str = "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}";
+
+        // Logs in before the first request, and again after the token expired
+        let (url, requests) = serve(vec![LOGIN_1, UNAUTHORIZED, LOGIN_2, OK, OK]);
+        let client = EspoApiClient::new(&url).set_auth(Auth::session("admin", "pass")).build();
+        assert!(client.session_user().is_none());
+
+        client.read("Contact", "1").await.unwrap();
+        client.clone().read("Contact", "2").await.unwrap();
+
+        let received: Vec<String> = requests.lock().unwrap().iter().map(|x| x.to_lowercase()).collect();
+        assert_eq!(5, received.len());
+        assert!(received[0].starts_with("get /api/v1/app/user"));
+        assert!(received[0].contains("espo-authorization: ywrtaw46cgfzcw==") && received[0].contains("espo-authorization-by-token: false"));
+        assert!(received[1].contains("espo-authorization: ywrtaw46dg9rmq==") && received[1].contains("espo-authorization-by-token: true"));
+        assert!(received[2].starts_with("get /api/v1/app/user"));
+        assert!(received[3].contains("espo-authorization: ywrtaw46dg9rmg=="));
+        assert!(received[4].starts_with("get /api/v1/contact/2") && received[4].contains("espo-authorization: ywrtaw46dg9rmg=="));
+
+        let user = client.session_user().unwrap();
+        assert_eq!(("1", "admin", Some("admin")), (user.id.as_str(), user.user_name.as_str(), user.user_type.as_deref()));
+        assert_eq!(Some(&serde_json::json!(true)), user.attributes.get("isActive"));
+
+        // Wrong credentials are not retried
+        let (url, requests) = serve(vec![UNAUTHORIZED]);
+        let client = EspoApiClient::new(&url).set_auth(Auth::session("admin", "wrong")).build();
+        assert!(matches!(client.read("Contact", "1").await, Err(EspoError::Unauthorized { .. })));
+        assert_eq!(1, requests.lock().unwrap().len());
+
+        let client = EspoApiClient::new(&url).set_auth(Auth::api_key("key")).build();
+        assert!(matches!(client.login().await, Err(EspoError::Auth(_))));
+    }
+
+    #[tokio::test]
+    async fn session_auth_retried() {
+        const LOGIN_1: &str = r#"{"token":"tok1","user":{"id":"1","userName":"admin"}}"#;
+        const LOGIN_2: &str = r#"{"token":"tok2","user":{"id":"1","userName":"admin"}}"#;
+
+        // The token is refreshed when it expires during a retried request, but only once
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response(StatusCode::OK, LOGIN_1)
+            .push_response(StatusCode::SERVICE_UNAVAILABLE, "")
+            .push_response(StatusCode::UNAUTHORIZED, "")
+            .push_response(StatusCode::OK, LOGIN_2)
+            .push_response(StatusCode::UNAUTHORIZED, "");
+        let client = EspoApiClient::new(URL)
+            .set_auth(Auth::session("admin", "pass"))
+            .set_retry_policy(RetryPolicy::new().set_initial_backoff(Duration::ZERO).build())
+            .set_transport(transport.clone())
+            .build();
+        assert!(matches!(client.read("Contact", "1").await, Err(EspoError::Unauthorized { .. })));
+        assert_eq!(5, transport.requests().len());
+
+        // Logging in counts towards the rate limit
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response(StatusCode::OK, LOGIN_1).push_response(StatusCode::OK, "{}");
+        let client = EspoApiClient::new(URL)
+            .set_auth(Auth::session("admin", "pass"))
+            .set_rate_limit(10.0, 1)
+            .set_transport(transport)
+            .build();
+        let start = Instant::now();
+        client.read("Contact", "1").await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn session_login_middleware() {
+        const LOGIN: &str = r#"{"token":"tok1","user":{"id":"1","userName":"admin"}}"#;
+
+        #[derive(Debug)]
+        struct Recorder(Arc<Mutex<Vec<(String, u32)>>>);
+
+        impl Middleware for Recorder {
+            fn before_send(&self, request: &mut EspoRequest) -> Result<(), EspoError> {
+                self.0.lock().unwrap().push((request.action.clone(), request.attempt));
+                request.set_header("X-Correlation-Id", "1")
+            }
+        }
+
+        // The login passes through the middleware, and is retried like any other request
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response(StatusCode::SERVICE_UNAVAILABLE, "")
+            .push_response(StatusCode::OK, LOGIN)
+            .push_response(StatusCode::OK, "{}")
+            .push_response(StatusCode::OK, LOGIN);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let client = EspoApiClient::new(URL)
+            .set_auth(Auth::session("admin", "pass"))
+            .set_retry_policy(RetryPolicy::new().set_initial_backoff(Duration::ZERO).build())
+            .add_middleware(Recorder(seen.clone()))
+            .set_transport(transport.clone())
+            .build();
+
+        let spawned = client.clone();
+        tokio::spawn(async move { spawned.read("Contact", "1").await }).await.unwrap().unwrap();
+        let expected = [("App/user", 1), ("App/user", 2), ("Contact/1", 1)].map(|(action, attempt)| (action.to_string(), attempt));
+        assert_eq!(expected.to_vec(), *seen.lock().unwrap());
+
+        let requests = transport.requests();
+        assert_eq!("1", requests[1].headers["X-Correlation-Id"]);
+        assert_eq!("false", requests[1].headers["Espo-Authorization-By-Token"]);
+
+        // Logging in again uses the password, not the cached token
+        client.login().await.unwrap();
+        let requests = transport.requests();
+        assert_eq!(base64::encode("admin:pass"), requests[3].headers["Espo-Authorization"]);
+        assert_eq!("false", requests[3].headers["Espo-Authorization-By-Token"]);
+    }
+
+    #[tokio::test]
+    async fn session_two_factor() {
+        const LOGIN: &str = r#"{"token":"tok1","user":{"id":"1","userName":"admin"}}"#;
+
+        let second_step = || {
+            let mut headers = HeaderMap::new();
+            headers.insert("X-Status-Reason", HeaderValue::from_static("second-step-required"));
+            headers
+        };
+
+        // The callback runs outside of the runtime's thread, so it may block
+        let calls = Arc::new(Mutex::new(0));
+        let counter = calls.clone();
+        let runtime_thread = std::thread::current().id();
+        let code = TwoFactorCode::callback(move || {
+            assert_ne!(runtime_thread, std::thread::current().id());
+            *counter.lock().unwrap() += 1;
+            Ok("123456".to_string())
+        });
+
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response_with_headers(StatusCode::UNAUTHORIZED, second_step(), "{}")
+            .push_response(StatusCode::OK, LOGIN);
+        let client = EspoApiClient::new(URL)
+            .set_auth(Auth::session_2fa("admin", "pass", code))
+            .set_transport(transport.clone())
+            .build();
+        assert_eq!("admin", client.login().await.unwrap().user_name);
+        assert_eq!(1, *calls.lock().unwrap());
+
+        let requests = transport.requests();
+        assert!(!requests[0].headers.contains_key("Espo-Authorization-Code"));
+        assert_eq!("123456", requests[1].headers["Espo-Authorization-Code"]);
+        assert_eq!(base64::encode("admin:pass"), requests[1].headers["Espo-Authorization"]);
+
+        // Without a code, the challenge is reported as is
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response_with_headers(StatusCode::UNAUTHORIZED, second_step(), "{}");
+        let client = EspoApiClient::new(URL).set_auth(Auth::session("admin", "pass")).set_transport(transport).build();
+        let error = client.login().await.unwrap_err();
+        assert!(matches!(error, EspoError::Unauthorized { .. }));
+        assert_eq!(Some("second-step-required"), error.status_reason());
+
+        let transport = Arc::new(MemoryTransport::new());
+        transport.push_response_with_headers(StatusCode::UNAUTHORIZED, second_step(), "{}");
+        let code = TwoFactorCode::callback(|| Err("no code entered".into()));
+        let client = EspoApiClient::new(URL)
+            .set_auth(Auth::session_2fa("admin", "pass", code))
+            .set_transport(transport.clone())
+            .build();
+        assert!(matches!(client.read("Contact", "1").await, Err(EspoError::Auth(_))));
+        assert_eq!(1, transport.requests().len());
+        assert!(!format!("{:?}", TwoFactorCode::fixed("123456")).contains("123456"));
+    }
+
+    #[test]
+    fn hmac_signer_vectors() {
+        // Computed independently with Python's hmac module
+        let vectors = [
+            (
+                "2e8f9b5f4c2a1d3e7b6a9c0d1e2f3a4b",
+                "9d8c7b6a5f4e3d2c1b0a9f8e7d6c5b4a",
+                "GET",
+                "Contact",
+                "MmU4ZjliNWY0YzJhMWQzZTdiNmE5YzBkMWUyZjNhNGI6iCJK5pcNiSUNVwkpQGobVbfJNlK9BCzxmUpPfYWdrGI=",
+            ),
+            ("api-key", "secret", "GET", "Contact/5f2b1a", "YXBpLWtleTpXD6ZBzPA53vqTYU3A7GSYQKaOstoNS9kuR5WAWp+dDA=="),
+            ("api-key", "secret", "POST", "Account", "YXBpLWtleTqv0GGOqKm1iSfIfoOkXzeNGueLuAaV/oL0hV/zdAThIw=="),
+            ("api-key", "", "DELETE", "Lead/1", "YXBpLWtleTqx6UZFN10Wn//5w+AljpL79ReN1rtQPjknf72EKhjDuA=="),
+        ];
+
+        for (api_key, secret_key, method, path, header) in vectors {
+            let signer = HmacSigner::new(api_key, secret_key);
+            assert_eq!(header, signer.sign(method, path).unwrap());
+            assert_eq!(Ok(()), signer.verify(method, path, header));
+        }
+    }
+
+    #[test]
+    fn hmac_signer_verify() {
+        let signer = HmacSigner::new("api-key", "secret");
+        let header = signer.sign("get", "/Contact/5f2b1a?select=name").unwrap();
+
+        // The method is case insensitive, and the leading slash and query string are not signed
+        assert_eq!("YXBpLWtleTpXD6ZBzPA53vqTYU3A7GSYQKaOstoNS9kuR5WAWp+dDA==", header);
+        assert_eq!(Ok(()), signer.verify("GET", "Contact/5f2b1a?select=id", &header));
+
+        assert_eq!(Err(HmacError::InvalidSignature), signer.verify("GET", "Contact/other", &header));
+        assert_eq!(Err(HmacError::InvalidSignature), HmacSigner::new("api-key", "other").verify("GET", "Contact/5f2b1a", &header));
+        assert_eq!(Err(HmacError::ApiKeyMismatch), HmacSigner::new("other", "secret").verify("GET", "Contact/5f2b1a", &header));
+        assert_eq!(Err(HmacError::MalformedHeader), signer.verify("GET", "Contact", "not base64!"));
+        assert_eq!(Err(HmacError::MalformedHeader), signer.verify("GET", "Contact", &base64::encode("no separator")));
+        assert!(!format!("{signer:?}").contains("api-key"));
+    }
+
+    #[test]
+    fn debug_redacts_credentials() {
+        let client = EspoApiClient::new(URL).set_auth(Auth::basic("username", "hunter2")).build();
+        let debug = format!("{client:?}");
+        assert!(debug.contains("username") && debug.contains("[REDACTED]"));
+        assert!(!debug.contains("hunter2"));
+
+        let client = EspoApiClient::new(URL).set_auth(Auth::hmac("my-api-key", "my-secret-key")).build();
+        let debug = format!("{client:?}");
+        for secret in ["my-api-key", "my-secret-key"] {
+            assert!(!debug.contains(secret));
+        }
+
+        let client = EspoApiClient::new(URL).set_auth(Auth::api_key("my-api-key")).build();
+        let mut request = client.new_request(Call::new(reqwest::Method::GET, "Contact")).unwrap();
+        client.auth.before_send(&mut request).unwrap();
+        assert!(request.headers["X-Api-Key"].is_sensitive());
+    }
+
     #[test]
     fn normalize_url() {
         let client = EspoApiClient::new(URL);
-        let normalized_url = client.normalize_url("Contact".to_string());
+        let normalized_url = client.normalize_url("Contact");
 
         assert_eq!(format!("{}{}Contact", URL, client.url_path), normalized_url)
     }
@@ -200,7 +811,7 @@ mod tests {
         let params = Params::new().set_offset(0).set_order(Order::Desc).build();
 
         let serialized = serialize(params).unwrap();
-        let serialized_split: Vec<_> = serialized.split("&").collect();
+        let serialized_split: Vec<_> = serialized.split('&').collect();
 
         let correct = vec!["order=desc", "offset=0"];
         assert!(assert_eq_unsorted_vec(&serialized_split, &correct))
@@ -210,7 +821,7 @@ mod tests {
     fn serialize_without_where_value() {
         let params = Params::new()
             .set_offset(0)
-            .set_where(vec![Where {
+            .set_where(vec![Where::Clause {
                 r#type: FilterType::IsTrue,
                 attribute: "exampleBoolean".to_string(),
                 value: None,
@@ -230,7 +841,7 @@ mod tests {
     fn serialize_with_where_string_value() {
         let params = Params::new()
             .set_offset(0)
-            .set_where(vec![Where {
+            .set_where(vec![Where::Clause {
                 r#type: FilterType::IsTrue,
                 attribute: "exampleBoolean".to_string(),
                 value: Some(Value::str("a")),
@@ -246,7 +857,7 @@ mod tests {
     fn serialize_with_where_array_value() {
         let params = Params::new()
             .set_offset(0)
-            .set_where(vec![Where {
+            .set_where(vec![Where::Clause {
                 r#type: FilterType::IsTrue,
                 attribute: "exampleBoolean".to_string(),
                 value: Some(Value::Array(Some(vec![
@@ -279,4 +890,1073 @@ mod tests {
         */
         assert_eq!("offset=0&where%5B0%5D%5Btype%5D=isTrue&where%5B0%5D%5Battribute%5D=exampleBoolean&where%5B0%5D%5Bvalue%5D%5B0%5D=a&where%5B0%5D%5Bvalue%5D%5B1%5D=b&where%5B0%5D%5Bvalue%5D%5B2%5D=c".to_string(), serialized);
     }
+
+    #[test]
+    fn serialize_bool_filter_list() {
+        let params = Params::new()
+            .set_bool_filter_list(vec![BoolFilter::OnlyMy, BoolFilter::Followed])
+            .build();
+
+        let serialized = serialize(params).unwrap();
+
+        assert_eq!(
+            "boolFilterList%5B%5D=onlyMy&boolFilterList%5B%5D=followed".to_string(),
+            serialized
+        );
+    }
+
+    #[test]
+    fn bool_filter_from_str() {
+        let params = Params::new()
+            .set_bool_filter_list(["onlyMy", "myCustomFilter"])
+            .build();
+
+        assert_eq!(
+            Some(vec![
+                BoolFilter::OnlyMy,
+                BoolFilter::Custom("myCustomFilter".to_string())
+            ]),
+            params.bool_filter_list
+        );
+    }
+
+    #[test]
+    fn serialize_with_where_or_group() {
+        let params = Params::new()
+            .set_where(vec![Where::or(vec![
+                Where::new(FilterType::Equals, "status", Some(Value::str("New"))),
+                Where::new(FilterType::Equals, "assignedUserId", Some(Value::str("1"))),
+            ])])
+            .build();
+
+        let serialized = serialize(params).unwrap();
+
+        /*
+           The left hand side has been created with the following PHP code:
+
+           $where = [
+               [
+                   'type' => 'or',
+                   'value' => [
+                       ['type' => 'equals', 'attribute' => 'status', 'value' => 'New'],
+                       ['type' => 'equals', 'attribute' => 'assignedUserId', 'value' => '1'],
+                   ]
+               ],
+           ];
+
+           echo http_build_query(['where' => $where]);
+        */
+        assert_eq!("where%5B0%5D%5Btype%5D=or&where%5B0%5D%5Bvalue%5D%5B0%5D%5Btype%5D=equals&where%5B0%5D%5Bvalue%5D%5B0%5D%5Battribute%5D=status&where%5B0%5D%5Bvalue%5D%5B0%5D%5Bvalue%5D=New&where%5B0%5D%5Bvalue%5D%5B1%5D%5Btype%5D=equals&where%5B0%5D%5Bvalue%5D%5B1%5D%5Battribute%5D=assignedUserId&where%5B0%5D%5Bvalue%5D%5B1%5D%5Bvalue%5D=1".to_string(), serialized);
+    }
+
+    #[test]
+    fn serialize_with_where_nested_groups() {
+        let params = Params::new()
+            .set_where(vec![
+                Where::new(FilterType::IsTrue, "a", None),
+                Where::not(vec![Where::and(vec![Where::new(FilterType::IsNull, "b", None)])]),
+            ])
+            .build();
+
+        let serialized = serialize(params).unwrap();
+
+        assert_eq!("where%5B0%5D%5Btype%5D=isTrue&where%5B0%5D%5Battribute%5D=a&where%5B1%5D%5Btype%5D=not&where%5B1%5D%5Bvalue%5D%5B0%5D%5Btype%5D=and&where%5B1%5D%5Bvalue%5D%5B0%5D%5Bvalue%5D%5B0%5D%5Btype%5D=isNull&where%5B1%5D%5Bvalue%5D%5B0%5D%5Bvalue%5D%5B0%5D%5Battribute%5D=b".to_string(), serialized);
+    }
+
+    #[test]
+    fn serialize_encodes_keys_and_values() {
+        let params = Params::new()
+            .set_select("name,émail")
+            .set_primary_filter("a&b")
+            .set_where(vec![Where::new(
+                FilterType::Equals,
+                "näme",
+                Some(Value::str("x=y #1")),
+            )])
+            .build();
+
+        let serialized = serialize(params).unwrap();
+
+        assert_eq!("select=name%2C%C3%A9mail&primaryFilter=a%26b&where%5B0%5D%5Btype%5D=equals&where%5B0%5D%5Battribute%5D=n%C3%A4me&where%5B0%5D%5Bvalue%5D=x%3Dy%20%231".to_string(), serialized);
+    }
+
+    #[test]
+    fn serialize_null_values() {
+        let params = Params::new()
+            .set_where(vec![Where::new(
+                FilterType::In,
+                "a",
+                Some(Value::array(vec![Value::String(None), Value::str("b"), Value::Null])),
+            )])
+            .build();
+
+        let serialized = serialize(params).unwrap();
+
+        // Like http_build_query, missing values are left out. Explicit nulls are sent as an empty value
+        assert_eq!(
+            "where%5B0%5D%5Btype%5D=in&where%5B0%5D%5Battribute%5D=a&where%5B0%5D%5Bvalue%5D%5B1%5D=b&where%5B0%5D%5Bvalue%5D%5B2%5D="
+                .to_string(),
+            serialized
+        );
+    }
+
+    #[test]
+    fn serialize_errors() {
+        let missing_attribute = Params::new()
+            .set_where(vec![Where::new(FilterType::IsTrue, "", None)])
+            .build();
+        assert_eq!(
+            Err(SerializeError::MissingAttribute(FilterType::IsTrue)),
+            serialize(missing_attribute)
+        );
+
+        let invalid_group = Params::new()
+            .set_where(vec![Where::new(FilterType::Or, "", Some(Value::str("a")))])
+            .build();
+        assert_eq!(
+            Err(SerializeError::InvalidGroup(FilterType::Or)),
+            serialize(invalid_group)
+        );
+
+        // An empty attribute is an error, and not mistaken for a group
+        let empty_attribute = Params::new()
+            .set_where(vec![Where::new(FilterType::Equals, "", Some(Value::str("a")))])
+            .build();
+        assert_eq!(
+            Err(SerializeError::MissingAttribute(FilterType::Equals)),
+            serialize(empty_attribute)
+        );
+    }
+
+    #[test]
+    fn filter_dsl() {
+        assert_eq!(
+            Where::new(FilterType::Equals, "status", Some(Value::str("New"))),
+            filter("status").eq("New")
+        );
+        assert_eq!(
+            Where::new(FilterType::IsTrue, "isActive", None),
+            filter("isActive").is_true()
+        );
+        assert_eq!(
+            Where::new(
+                FilterType::Between,
+                "amount",
+                Some(Value::array(vec![Value::int(1), Value::int(5)]))
+            ),
+            filter("amount").between(1, 5)
+        );
+        assert_eq!(
+            Where::new(
+                FilterType::ArrayAnyOf,
+                "tags",
+                Some(Value::array(vec![Value::str("a"), Value::str("b")]))
+            ),
+            filter("tags").any_of(["a", "b"])
+        );
+        assert_eq!(
+            Where::new(FilterType::LastXDays, "createdAt", Some(Value::int(7))),
+            filter("createdAt").last_x_days(7)
+        );
+    }
+
+    #[test]
+    fn filter_dsl_groups() {
+        assert_eq!(
+            Where::or(vec![
+                Where::new(FilterType::Equals, "a", Some(Value::bool(true))),
+                Where::not(vec![Where::new(FilterType::IsNull, "b", None)]),
+            ]),
+            or([filter("a").eq(true), not([filter("b").is_null()])])
+        );
+        assert!(matches!(and([filter("a").past()]), Where::Group { r#type: GroupType::And, .. }));
+    }
+
+    #[test]
+    fn serialize_typed_values() {
+        let date = EspoDate::new(2024, 2, 29).unwrap();
+        let params = Params::new()
+            .set_where([
+                filter("amount").gt(1.5),
+                filter("closeDate").eq(date),
+                filter("createdAt").lt(EspoDateTime::new(date, 13, 5, 0).unwrap()),
+                Where::new(FilterType::Equals, "a", Some(Value::Null)),
+            ])
+            .build();
+
+        let serialized = serialize(params).unwrap();
+
+        assert_eq!(
+            "where%5B0%5D%5Btype%5D=greaterThan&where%5B0%5D%5Battribute%5D=amount&where%5B0%5D%5Bvalue%5D=1.5\
+            &where%5B1%5D%5Btype%5D=equals&where%5B1%5D%5Battribute%5D=closeDate&where%5B1%5D%5Bvalue%5D=2024-02-29\
+            &where%5B2%5D%5Btype%5D=lessThan&where%5B2%5D%5Battribute%5D=createdAt&where%5B2%5D%5Bvalue%5D=2024-02-29%2013%3A05%3A00\
+            &where%5B3%5D%5Btype%5D=equals&where%5B3%5D%5Battribute%5D=a&where%5B3%5D%5Bvalue%5D="
+                .to_string(),
+            serialized
+        );
+
+        let nan = Params::new().set_where([filter("amount").gt(f64::NAN)]).build();
+        assert!(matches!(serialize(nan), Err(SerializeError::NonFiniteFloat(_))));
+    }
+
+    #[test]
+    fn date_parse() {
+        let date: EspoDate = "2023-12-01".parse().unwrap();
+        assert_eq!(EspoDate::new(2023, 12, 1), Some(date));
+        assert_eq!("2023-12-01", date.to_string());
+
+        let datetime: EspoDateTime = "2023-12-01 08:30:59".parse().unwrap();
+        assert_eq!(EspoDateTime::new(date, 8, 30, 59), Some(datetime));
+        assert_eq!("\"2023-12-01 08:30:59\"", serde_json::to_string(&datetime).unwrap());
+
+        assert_eq!(None, EspoDate::new(2023, 2, 29));
+        assert!("2023-13-01".parse::<EspoDate>().is_err());
+        assert!("2023-12-01".parse::<EspoDateTime>().is_err());
+        assert!("2023-12-01 24:00:00".parse::<EspoDateTime>().is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn date_chrono() {
+        use chrono::{FixedOffset, NaiveDate, TimeZone};
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        assert_eq!(EspoDate::new(2024, 3, 4), date.try_into().ok());
+
+        let datetime = FixedOffset::east_opt(2 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 3, 4, 1, 0, 0)
+            .unwrap();
+        assert_eq!("2024-03-03 23:00:00", EspoDateTime::try_from(datetime).unwrap().to_string());
+
+        let out_of_range = NaiveDate::from_ymd_opt(10000, 1, 1).unwrap();
+        assert!(EspoDate::try_from(out_of_range).is_err());
+        assert!(EspoDateTime::try_from(out_of_range.and_hms_opt(0, 0, 0).unwrap()).is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn date_time() {
+        use time::{Date, Month, PrimitiveDateTime, Time, UtcOffset};
+
+        let date = Date::from_calendar_date(2024, Month::March, 4).unwrap();
+        assert_eq!(EspoDate::new(2024, 3, 4), date.try_into().ok());
+
+        let datetime = PrimitiveDateTime::new(date, Time::from_hms(1, 0, 0).unwrap())
+            .assume_offset(UtcOffset::from_hms(2, 0, 0).unwrap());
+        assert_eq!("2024-03-03 23:00:00", EspoDateTime::try_from(datetime).unwrap().to_string());
+
+        let out_of_range = Date::from_calendar_date(-1, Month::December, 31).unwrap();
+        assert!(EspoDate::try_from(out_of_range).is_err());
+        assert!(EspoDateTime::try_from(PrimitiveDateTime::new(out_of_range, Time::MIDNIGHT)).is_err());
+
+        // In UTC, this is past the last date `time` supports
+        let last = Date::from_calendar_date(9999, Month::December, 31).unwrap();
+        let past_range = PrimitiveDateTime::new(last, Time::from_hms(23, 0, 0).unwrap())
+            .assume_offset(UtcOffset::from_hms(-2, 0, 0).unwrap());
+        assert!(EspoDateTime::try_from(past_range).is_err());
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let policy = RetryPolicy::new()
+            .set_initial_backoff(Duration::from_millis(100))
+            .set_max_backoff(Duration::from_millis(300))
+            .set_jitter(false)
+            .build();
+
+        assert_eq!(Duration::from_millis(100), policy.backoff(1, None));
+        assert_eq!(Duration::from_millis(200), policy.backoff(2, None));
+        assert_eq!(Duration::from_millis(300), policy.backoff(3, None));
+        assert_eq!(Duration::from_millis(300), policy.backoff(40, None));
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(reqwest::header::RETRY_AFTER, "0".parse().unwrap());
+        assert_eq!(Duration::ZERO, policy.backoff(2, Some(&headers)));
+
+        let jittered = policy.build().set_jitter(true).backoff(2, None);
+        assert!(jittered >= Duration::from_millis(100) && jittered <= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn retry_methods() {
+        let policy = RetryPolicy::new();
+        assert!(policy.retries_method(&reqwest::Method::GET));
+        assert!(policy.retries_method(&reqwest::Method::DELETE));
+        assert!(!policy.retries_method(&reqwest::Method::POST));
+        assert!(!policy.retries_method(&reqwest::Method::PATCH));
+        assert!(policy.retries_status(reqwest::StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!policy.retries_status(reqwest::StatusCode::INTERNAL_SERVER_ERROR));
+    }
+
+    #[tokio::test]
+    async fn retry_request() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response(StatusCode::SERVICE_UNAVAILABLE, "")
+            .push_response(StatusCode::SERVICE_UNAVAILABLE, "")
+            .push_response(StatusCode::OK, "{}");
+        let client = EspoApiClient::new(URL)
+            .set_auth(Auth::hmac("key", "secret"))
+            .set_retry_policy(RetryPolicy::new().set_initial_backoff(Duration::ZERO).build())
+            .set_transport(transport.clone())
+            .build();
+
+        let response = client.read("Contact", "1").await.unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+
+        let requests = transport.requests();
+        assert_eq!(3, requests.len());
+        assert!(requests.iter().all(|x| x.headers.contains_key(HMAC_HEADER)));
+        assert_eq!(vec![1, 2, 3], requests.iter().map(|x| x.attempt).collect::<Vec<_>>());
+
+        // POST is not retried by default
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response(StatusCode::SERVICE_UNAVAILABLE, "")
+            .push_response(StatusCode::OK, "{}");
+        let client = client.build().set_transport(transport.clone()).build();
+        let error = client.create("Contact", serde_json::json!({})).await.unwrap_err();
+        assert_eq!(Some(StatusCode::SERVICE_UNAVAILABLE), error.status());
+        assert_eq!(1, transport.requests().len());
+
+        // Retry-After replaces the backoff of the policy
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("0"));
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response_with_headers(StatusCode::TOO_MANY_REQUESTS, headers, "")
+            .push_response(StatusCode::OK, "{}");
+        let client = client
+            .build()
+            .set_retry_policy(RetryPolicy::new().set_initial_backoff(Duration::from_secs(60)).set_jitter(false).build())
+            .set_transport(transport.clone())
+            .build();
+        let response = tokio::time::timeout(Duration::from_secs(5), client.read("Contact", "1")).await.unwrap().unwrap();
+        assert_eq!(StatusCode::OK, response.status());
+        assert_eq!(2, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn middleware() {
+        #[derive(Debug, Default)]
+        struct Recorder(Mutex<Vec<(u32, Option<u16>)>>);
+
+        impl Middleware for Arc<Recorder> {
+            fn before_send(&self, request: &mut EspoRequest) -> Result<(), EspoError> {
+                // Runs before the built-in auth, which would replace this header
+                request.set_header("X-Api-Key", "overwritten")?;
+                request.set_header("X-Correlation-Id", &format!("attempt-{}", request.attempt))
+            }
+
+            fn after_receive(&self, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> AfterReceive {
+                let status = response.ok().map(|x| x.status.as_u16());
+                self.0.lock().unwrap().push((request.attempt, status));
+                AfterReceive::Continue
+            }
+        }
+
+        #[derive(Debug)]
+        struct Reject;
+
+        impl Middleware for Reject {
+            fn before_send(&self, _: &mut EspoRequest) -> Result<(), EspoError> {
+                Err(EspoError::Middleware("rejected".into()))
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response(StatusCode::SERVICE_UNAVAILABLE, "")
+            .push_response(StatusCode::OK, "{}");
+        let client = EspoApiClient::new(URL)
+            .set_auth(Auth::api_key("key"))
+            .set_retry_policy(RetryPolicy::new().set_initial_backoff(Duration::ZERO).build())
+            .add_middleware(recorder.clone())
+            .set_transport(transport.clone())
+            .build();
+
+        client.read("Contact", "1").await.unwrap();
+        assert_eq!(vec![(1, Some(503)), (2, Some(200))], *recorder.0.lock().unwrap());
+
+        let requests = transport.requests();
+        assert_eq!("attempt-1", requests[0].headers["X-Correlation-Id"]);
+        assert_eq!("attempt-2", requests[1].headers["X-Correlation-Id"]);
+        assert!(requests.iter().all(|x| x.headers["X-Api-Key"] == "key"));
+
+        // A rejected request is not sent
+        let client = client.build().add_middleware(Reject).build();
+        assert!(matches!(client.read("Contact", "1").await, Err(EspoError::Middleware(_))));
+        assert_eq!(2, recorder.0.lock().unwrap().len());
+        assert_eq!(2, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn string_keyed_entities() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response(StatusCode::OK, r#"{"id":"1","name":"Acme"}"#)
+            .push_response(StatusCode::OK, r#"{"total":1,"list":[{"id":"1","name":"Acme"}]}"#);
+
+        let client = EspoApiClient::new("https://espocrm.example.com").set_transport(transport.clone()).build();
+
+        let record: serde_json::Value = client.get_of("CAccount", "1").await.unwrap();
+        assert_eq!("Acme", record["name"]);
+
+        let list: ListResponse<serde_json::Value> = client.list_of("CAccount", Params::new()).await.unwrap();
+        assert_eq!(1, list.total);
+
+        let actions: Vec<String> = transport.requests().into_iter().map(|x| x.action).collect();
+        assert_eq!(vec!["CAccount/1", "CAccount"], actions);
+    }
+
+    #[tokio::test]
+    async fn memory_transport() {
+        const LOGIN: &str = r#"{"token":"token-1","user":{"id":"1","userName":"admin"}}"#;
+        const RELOGIN: &str = r#"{"token":"token-2","user":{"id":"1","userName":"admin"}}"#;
+
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_response(StatusCode::OK, LOGIN)
+            .push_response(StatusCode::UNAUTHORIZED, "")
+            .push_response(StatusCode::OK, RELOGIN)
+            .push_response(StatusCode::OK, r#"{"id":"2"}"#);
+
+        let client = EspoApiClient::new("https://espocrm.example.com")
+            .set_auth(Auth::session("admin", "password"))
+            .set_timeout(Duration::from_secs(5))
+            .set_transport(transport.clone())
+            .build();
+
+        let created: serde_json::Value = client
+            .create("Contact", serde_json::json!({"name": "Alice"}))
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!("2", created["id"]);
+
+        let requests = transport.requests();
+        let actions: Vec<(&str, &str)> = requests.iter().map(|x| (x.method.as_str(), x.action.as_str())).collect();
+        assert_eq!(vec![("GET", "App/user"), ("POST", "Contact"), ("GET", "App/user"), ("POST", "Contact")], actions);
+
+        let token = |token: &str| base64::encode(format!("admin:{token}"));
+        assert_eq!(token("token-1"), requests[1].headers["Espo-Authorization"]);
+        assert_eq!(token("token-2"), requests[3].headers["Espo-Authorization"]);
+        assert_eq!(Some(br#"{"name":"Alice"}"#.as_slice()), requests[3].body.as_deref());
+        assert_eq!(Some(Duration::from_secs(5)), requests[3].timeout);
+
+        // Setting a reqwest client replaces the transport
+        let client = client.build().set_http_client(reqwest::Client::new()).build();
+        assert!(client.transport.is_none());
+
+        // Errors are returned in order as well, and an empty queue fails the request instead of panicking
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_error(EspoError::CustomTransport("connection reset".into()))
+            .push_response(StatusCode::OK, "{}");
+        let client = EspoApiClient::new("https://espocrm.example.com").set_transport(transport.clone()).build();
+
+        let error = client.read("Contact", "1").await.unwrap_err();
+        assert!(matches!(&error, EspoError::CustomTransport(e) if e.to_string() == "connection reset"));
+        client.read("Contact", "1").await.unwrap();
+        assert!(matches!(client.read("Contact", "1").await, Err(EspoError::CustomTransport(_))));
+        assert_eq!(3, transport.requests().len());
+    }
+
+    #[tokio::test]
+    async fn http_client_kept() {
+        const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}";
+        let (url, requests) = serve(vec![OK]);
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-custom", "1".parse().unwrap());
+        let http_client = reqwest::Client::builder().default_headers(headers).build().unwrap();
+
+        // Changing the connection options afterwards doesn't replace the client
+        let client = EspoApiClient::new(&url)
+            .set_http_client(http_client)
+            .set_connect_timeout(Duration::from_secs(5))
+            .set_read_timeout(Duration::from_secs(5))
+            .build();
+        client.read("Contact", "1").await.unwrap();
+
+        assert!(requests.lock().unwrap()[0].contains("x-custom: 1"));
+    }
+
+    #[tokio::test]
+    async fn timeout() {
+        // Accept connections, but never respond
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let _connections: Vec<_> = listener.incoming().collect();
+        });
+
+        let client = EspoApiClient::new(&url).set_timeout(Duration::from_secs(60)).build();
+        let error = client
+            .with_timeout(Duration::from_millis(50))
+            .read("Contact", "1")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, EspoError::Timeout(_)));
+        assert_eq!(Some(Duration::from_secs(60)), client.timeout);
+
+        let client = client.build().set_read_timeout(Duration::from_millis(50)).build();
+        let error = client.read("Contact", "1").await.unwrap_err();
+        assert!(matches!(error, EspoError::Timeout(_)));
+    }
+
+    #[tokio::test]
+    async fn in_flight_until_body_read() {
+        // Send the headers right away, but the body only after a delay
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let received = events.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (mut stream, events) = (stream.unwrap(), received.clone());
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).unwrap() > 2 {
+                        line.clear();
+                    }
+
+                    events.lock().unwrap().push("request");
+                    stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\n{\"id\":").unwrap();
+                    stream.flush().unwrap();
+                    std::thread::sleep(Duration::from_millis(200));
+                    events.lock().unwrap().push("body");
+                    stream.write_all(b"\"1\"}").unwrap();
+                });
+            }
+        });
+
+        #[derive(serde::Deserialize)]
+        struct Contact {
+            id: String,
+        }
+
+        impl Entity for Contact {
+            const ENTITY_TYPE: &'static str = "Contact";
+            const FIELDS: &'static [&'static str] = &["id"];
+        }
+
+        let client = EspoApiClient::new(&url).set_max_in_flight(1).build();
+        let (a, b) = tokio::join!(client.get::<Contact, _>("1"), client.get::<Contact, _>("1"));
+        assert_eq!("1", a.unwrap().id);
+        assert_eq!("1", b.unwrap().id);
+
+        // The second request is only sent once the body of the first has been read
+        assert_eq!(vec!["request", "body", "request", "body"], *events.lock().unwrap());
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn blocking_client() {
+        use crate::blocking::EspoBlockingClient;
+
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Contact {
+            id: String,
+        }
+
+        impl Entity for Contact {
+            const ENTITY_TYPE: &'static str = "Contact";
+            const FIELDS: &'static [&'static str] = &["id"];
+        }
+
+        const UNAVAILABLE: &str = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        const FIRST_PAGE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 42\r\nConnection: close\r\n\r\n{\"total\":3,\"list\":[{\"id\":\"a\"},{\"id\":\"b\"}]}";
+        const LAST_PAGE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 31\r\nConnection: close\r\n\r\n{\"total\":3,\"list\":[{\"id\":\"c\"}]}";
+
+        let (url, requests) = serve(vec![UNAVAILABLE, FIRST_PAGE, LAST_PAGE]);
+        let client = EspoBlockingClient::new(&url)
+            .set_auth(Auth::hmac("key", "secret"))
+            .set_retry_policy(RetryPolicy::new().set_initial_backoff(Duration::ZERO).build())
+            .build();
+
+        let ids: Vec<String> = client
+            .list_iter::<Contact>(Params::new(), PaginationOptions::new().set_page_size(2).build())
+            .map(|x| x.unwrap().id)
+            .collect();
+        assert_eq!(vec!["a", "b", "c"], ids);
+
+        let received = requests.lock().unwrap().clone();
+        assert_eq!(3, received.len());
+        assert!(received.iter().all(|x| x.to_lowercase().contains("x-hmac-authorization")));
+        assert!(received[2].starts_with("GET /api/v1/Contact?offset=2&maxSize=2 "));
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn blocking_in_flight() {
+        use crate::blocking::InFlight;
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let in_flight = Arc::new(InFlight::new(1));
+        let acquired = Arc::new(AtomicBool::new(false));
+        let permit = in_flight.acquire();
+
+        let waiting = {
+            let (in_flight, acquired) = (in_flight.clone(), acquired.clone());
+            std::thread::spawn(move || {
+                let _permit = in_flight.acquire();
+                acquired.store(true, Ordering::SeqCst);
+            })
+        };
+
+        // The second request waits until the first one has finished
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!acquired.load(Ordering::SeqCst));
+        drop(permit);
+        waiting.join().unwrap();
+        assert!(acquired.load(Ordering::SeqCst));
+    }
+
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    #[test]
+    fn tls_options() {
+        const CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----\n\
+MIIBhTCCASugAwIBAgIUP9N1OYQORoLJAwLdMtd9fUb+pk4wCgYIKoZIzj0EAwIw\n\
+FzEVMBMGA1UEAwwMZXNwb2NybS50ZXN0MCAXDTI2MTAxNzE4MDQxNFoYDzIxMjYw\n\
+OTIzMTgwNDE0WjAXMRUwEwYDVQQDDAxlc3BvY3JtLnRlc3QwWTATBgcqhkjOPQIB\n\
+BggqhkjOPQMBBwNCAATybD0JLszY1vKPYV51e6Ld7KfZZIStBj42e77/b38GrEZr\n\
+X28KhNu5EZ48rrwgiHlR0b9HP/kVDPftJ2MIEvuSo1MwUTAdBgNVHQ4EFgQUaGBk\n\
+j2n1Mf/FLfw3bLABLKb/w1IwHwYDVR0jBBgwFoAUaGBkj2n1Mf/FLfw3bLABLKb/\n\
+w1IwDwYDVR0TAQH/BAUwAwEB/zAKBggqhkjOPQQDAgNIADBFAiAa4mr0j6a2nWmq\n\
+linNkGwzeg2Tn1EimUOrdZ0Q49ZbBgIhAKcGp1XW8F7WepHVQ6ax7Cpj+8wiAsVp\n\
+3wudUTdT9XOX\n\
+-----END CERTIFICATE-----";
+
+        let certificate = crate::Certificate::from_pem(CERTIFICATE.as_bytes()).unwrap();
+        let client = EspoApiClient::new(URL)
+            .add_root_certificate(certificate)
+            .set_accept_invalid_certs(true)
+            .build();
+
+        assert_eq!(1, client.root_certificates.len());
+        assert!(client.accept_invalid_certs);
+        assert!(!EspoApiClient::new(URL).accept_invalid_certs);
+    }
+
+    #[test]
+    fn rate_limit_token_bucket() {
+        let limiter = RateLimiter::new(10.0, 2);
+        let start = Instant::now();
+
+        // The burst is available immediately, after which tokens are refilled every 100ms
+        assert_eq!(Duration::ZERO, limiter.reserve(start));
+        assert_eq!(Duration::ZERO, limiter.reserve(start));
+        assert_eq!(Duration::from_millis(100), limiter.reserve(start));
+        assert_eq!(Duration::from_millis(200), limiter.reserve(start));
+
+        // After a long pause, no more than the burst is available
+        let later = start + Duration::from_secs(10);
+        assert_eq!(Duration::ZERO, limiter.reserve(later));
+        assert_eq!(Duration::ZERO, limiter.reserve(later));
+        assert_eq!(Duration::from_millis(100), limiter.reserve(later));
+
+        let tiny = RateLimiter::new(1e-300, 1);
+        assert_eq!(Duration::ZERO, tiny.reserve(start));
+        assert_eq!(Duration::MAX, tiny.reserve(start));
+    }
+
+    #[test]
+    fn rate_limit_shared_by_clones() {
+        let client = EspoApiClient::new(URL)
+            .set_rate_limit(5.0, 1)
+            .set_max_in_flight(4)
+            .build();
+        let clone = client.clone();
+
+        assert!(std::sync::Arc::ptr_eq(
+            client.rate_limiter.as_ref().unwrap(),
+            clone.rate_limiter.as_ref().unwrap()
+        ));
+        assert!(std::sync::Arc::ptr_eq(client.in_flight.as_ref().unwrap(), clone.in_flight.as_ref().unwrap()));
+        assert_eq!(4, clone.in_flight.unwrap().available_permits());
+
+        let unlimited = client.build().set_rate_limit(0.0, 1).set_max_in_flight(0).build();
+        assert!(unlimited.rate_limiter.is_none() && unlimited.in_flight.is_none());
+    }
+
+    #[test]
+    fn error_duplicate_bare_array() {
+        let error = EspoError::from_status(
+            reqwest::StatusCode::CONFLICT,
+            Some("Duplicate".to_string()),
+            r#"[{"id":"a"},{"id":"b"}]"#,
+        );
+
+        match &error {
+            EspoError::Duplicate { records, .. } => assert_eq!(2, records.len()),
+            e => panic!("Expected Duplicate, got {e:?}"),
+        }
+        assert_eq!(Some("Duplicate"), error.status_reason());
+    }
+
+    #[test]
+    fn error_duplicate_wrapped_data() {
+        let error = EspoError::from_status(
+            reqwest::StatusCode::CONFLICT,
+            None,
+            r#"{"reason":"Duplicate","data":[{"id":"a"}]}"#,
+        );
+
+        match error {
+            EspoError::Duplicate { records, .. } => assert_eq!(1, records.len()),
+            e => panic!("Expected Duplicate, got {e:?}"),
+        }
+    }
+
+    #[test]
+    fn error_status_mapping() {
+        let validation = EspoError::from_status(reqwest::StatusCode::BAD_REQUEST, None, "");
+        assert!(matches!(validation, EspoError::Validation { body: None, .. }));
+
+        let unauthorized = EspoError::from_status(reqwest::StatusCode::UNAUTHORIZED, None, "");
+        assert!(matches!(unauthorized, EspoError::Unauthorized { .. }));
+
+        let forbidden = EspoError::from_status(reqwest::StatusCode::FORBIDDEN, None, "");
+        assert!(matches!(forbidden, EspoError::Forbidden { .. }));
+
+        let not_found = EspoError::from_status(reqwest::StatusCode::NOT_FOUND, None, "");
+        assert!(matches!(not_found, EspoError::NotFound { .. }));
+
+        let server = EspoError::from_status(reqwest::StatusCode::BAD_GATEWAY, None, "");
+        assert_eq!(Some(reqwest::StatusCode::BAD_GATEWAY), server.status());
+        assert!(matches!(server, EspoError::Server { .. }));
+    }
+
+    #[tokio::test]
+    async fn error_unreadable_body() {
+        // The connection closes before the announced body was sent
+        const TRUNCATED: &str = "HTTP/1.1 404 Not Found\r\nX-Status-Reason: Gone\r\nContent-Length: 100\r\nConnection: close\r\n\r\n{}";
+
+        let (url, _) = serve(vec![TRUNCATED]);
+        let error = EspoApiClient::new(&url).read("Contact", "1").await.unwrap_err();
+
+        assert!(matches!(error, EspoError::NotFound { .. }));
+        assert_eq!(Some("Gone"), error.status_reason());
+    }
+
+    #[test]
+    fn deserialize_list_response() {
+        #[derive(serde::Deserialize, Debug, PartialEq)]
+        struct Contact {
+            id: String,
+        }
+
+        let response: ListResponse<Contact> =
+            serde_json::from_str(r#"{"total":2,"list":[{"id":"a"},{"id":"b"}]}"#).unwrap();
+
+        assert_eq!(2, response.total);
+        assert_eq!(
+            vec![Contact { id: "a".to_string() }, Contact { id: "b".to_string() }],
+            response.list
+        );
+    }
+
+    #[test]
+    fn pagination_offset() {
+        let options = PaginationOptions::new().set_page_size(2).build();
+        let mut state = PageState::new(Params::new().set_offset(1).build(), options);
+
+        let params = state.next_params().unwrap();
+        assert_eq!(Some(1), params.offset);
+        assert_eq!(Some(2), params.max_size);
+
+        let page = vec![serde_json::json!({"id": "a"}), serde_json::json!({"id": "b"})];
+        state.advance(5, &page).unwrap();
+        assert_eq!(Some(3), state.next_params().unwrap().offset);
+
+        state.advance(5, &page).unwrap();
+        assert!(state.next_params().is_none());
+    }
+
+    #[test]
+    fn pagination_short_page() {
+        let options = PaginationOptions::new().set_page_size(2).build();
+        let mut state = PageState::new(Params::new(), options);
+
+        state.advance(-1, &[serde_json::json!({"id": "a"})]).unwrap();
+        assert!(state.next_params().is_none());
+    }
+
+    #[test]
+    fn pagination_stable_id() {
+        let options = PaginationOptions::new()
+            .set_page_size(2)
+            .set_stable_order(StableOrder::Id)
+            .build();
+        let mut state = PageState::new(Params::new().set_offset(1).build(), options);
+
+        let params = state.next_params().unwrap();
+        assert_eq!(Some("id".to_string()), params.order_by);
+        assert_eq!(Some(Order::Asc), params.order);
+        assert_eq!(Some(1), params.offset);
+        assert_eq!(None, params.r#where);
+
+        // The first total counts the records before the offset as well
+        let page = vec![serde_json::json!({"id": "a"}), serde_json::json!({"id": "b"})];
+        state.advance(4, &page).unwrap();
+
+        let params = state.next_params().unwrap();
+        assert_eq!(None, params.offset);
+        assert_eq!(
+            Some(vec![Where::new(FilterType::GreaterThan, "id", Some(Value::str("b")))]),
+            params.r#where
+        );
+
+        state.advance(1, &[serde_json::json!({"id": "c"})]).unwrap();
+        assert!(state.next_params().is_none());
+    }
+
+    #[test]
+    fn pagination_page_size_clamped() {
+        let options = PaginationOptions::new().set_page_size(1000).build();
+        assert_eq!(200, options.page_size);
+
+        let options = PaginationOptions { page_size: 0, ..PaginationOptions::new() };
+        let state = PageState::new(Params::new(), options);
+        assert_eq!(Some(1), state.next_params().unwrap().max_size);
+    }
+
+    #[test]
+    fn derive_entity() {
+        #[allow(unused)]
+        #[derive(espocrm_rs_derive::Entity, serde::Deserialize)]
+        #[espo(entity = "Contact")]
+        #[serde(rename_all = "camelCase")]
+        struct Contact {
+            id: String,
+            first_name: String,
+            #[serde(rename = "cCustomerNumber")]
+            customer_number: String,
+            #[serde(rename = "emailAddress")]
+            email: String,
+            #[espo(skip)]
+            #[serde(default)]
+            local_only: String,
+        }
+
+        assert_eq!("Contact", Contact::ENTITY_TYPE);
+        assert_eq!(
+            &["id", "firstName", "cCustomerNumber", "emailAddress"],
+            Contact::FIELDS
+        );
+        assert_eq!("id,firstName,cCustomerNumber,emailAddress", Contact::select());
+
+        // The selected attributes are the ones serde reads the record from
+        let record = serde_json::json!({"id": "1", "firstName": "Alice", "cCustomerNumber": "42", "emailAddress": "a@example.com"});
+        let contact: Contact = serde_json::from_value(record).unwrap();
+        assert_eq!("Alice", contact.first_name);
+
+        // Without rename_all, fields keep their Rust names, as serde does
+        #[allow(unused)]
+        #[derive(espocrm_rs_derive::Entity, serde::Deserialize)]
+        struct Plain {
+            id: String,
+            first_name: String,
+        }
+
+        assert_eq!(&["id", "first_name"], Plain::FIELDS);
+    }
+
+    #[test]
+    fn derive_entity_serde_attributes() {
+        #[allow(unused)]
+        #[derive(espocrm_rs_derive::Entity, serde::Deserialize, serde::Serialize)]
+        #[serde(rename_all = "PascalCase", deny_unknown_fields)]
+        struct Custom {
+            #[serde(rename = "id")]
+            id: String,
+            account_name: String,
+            #[serde(default, rename(serialize = "cOut", deserialize = "cIn"))]
+            renamed: String,
+            #[serde(skip_serializing, default)]
+            created_at: String,
+            #[serde(skip_deserializing)]
+            local_only: String,
+        }
+
+        // Read-only fields are still selected, fields which are never deserialized are not
+        assert_eq!(&["id", "AccountName", "cIn", "CreatedAt"], Custom::FIELDS);
+    }
+
+    #[test]
+    fn derive_entity_default_type() {
+        #[allow(unused)]
+        #[derive(espocrm_rs_derive::Entity)]
+        struct Account {
+            name: String,
+        }
+
+        assert_eq!("Account", Account::ENTITY_TYPE);
+    }
+
+    const METADATA: &str = r#"{
+        "entityDefs": {
+            "Contact": {
+                "fields": {
+                    "firstName": { "type": "varchar", "maxLength": 100 },
+                    "status": { "type": "enum", "options": ["", "New", "In Process"] },
+                    "account": { "type": "link" },
+                    "teams": { "type": "linkMultiple" },
+                    "type": { "type": "varchar" },
+                    "billingAddress": { "type": "address" },
+                    "legacy": { "type": "bool", "disabled": true }
+                },
+                "links": {
+                    "account": { "type": "belongsTo", "entity": "Account", "foreign": "contacts" }
+                }
+            },
+            "Account": {
+                "fields": { "name": { "type": "varchar" } }
+            }
+        }
+    }"#;
+
+    #[test]
+    fn codegen_struct() {
+        let code = generate_entities_from_json(METADATA, &CodegenOptions::new()).unwrap();
+
+        assert!(code.contains("pub struct Contact {"));
+        assert!(code.contains("pub first_name: Option<String>,"));
+        assert!(code.contains("pub account_id: Option<String>,"));
+        assert!(code.contains("pub account_name: Option<String>,"));
+        assert!(code.contains("pub teams_ids: Option<Vec<String>>,"));
+        assert!(code.contains("pub r#type: Option<String>,"));
+        assert!(!code.contains("billing_address"));
+        assert!(!code.contains("legacy"));
+        assert!(code.contains(r#"const ENTITY_TYPE: &'static str = "Contact";"#));
+        assert!(code.contains("pub struct Account {"));
+    }
+
+    #[test]
+    fn codegen_enum() {
+        let code = generate_entities_from_json(METADATA, &CodegenOptions::new()).unwrap();
+
+        assert!(code.contains("pub status: Option<ContactStatus>,"));
+        assert!(code.contains("pub enum ContactStatus {"));
+        assert!(code.contains("#[serde(rename = \"\")]\n    Empty,"));
+        assert!(code.contains("#[serde(rename = \"In Process\")]\n    InProcess,"));
+    }
+
+    /// The output of the code generator for [METADATA], checked by `codegen_compiles`
+    #[allow(dead_code)]
+    mod generated {
+        include!("../tests/fixtures/entities.rs");
+    }
+
+    #[test]
+    fn codegen_compiles() {
+        use generated::{Contact, ContactStatus};
+
+        let code = generate_entities_from_json(METADATA, &CodegenOptions::new()).unwrap();
+        assert_eq!(include_str!("../tests/fixtures/entities.rs"), code);
+
+        let json = r#"{"id":"1","status":"Converted"}"#;
+        let contact: Contact = serde_json::from_str(json).unwrap();
+        assert_eq!(Some(ContactStatus::Unknown("Converted".to_string())), contact.status);
+        assert_eq!(json, serde_json::to_string(&contact).unwrap());
+
+        let contact: Contact = serde_json::from_str(r#"{"status":"In Process"}"#).unwrap();
+        assert_eq!(Some(ContactStatus::InProcess), contact.status);
+    }
+
+    #[test]
+    fn codegen_filter_entities() {
+        let options = CodegenOptions::new().set_entities(&["Account"]).build();
+        let code = generate_entities_from_json(METADATA, &options).unwrap();
+
+        assert!(code.contains("pub struct Account {"));
+        assert!(!code.contains("pub struct Contact {"));
+    }
+
+    #[test]
+    fn metadata_model() {
+        let metadata: Metadata = serde_json::from_str(METADATA).unwrap();
+        let contact = metadata.entity("Contact").unwrap();
+
+        let first_name = &contact.fields["firstName"];
+        assert_eq!(FieldType::Varchar, first_name.field_type);
+        assert_eq!(Some(100), first_name.max_length);
+
+        let status = &contact.fields["status"];
+        assert_eq!(FieldType::Enum, status.field_type);
+        assert_eq!(Some(3), status.options.as_ref().map(|x| x.len()));
+
+        let account = &contact.links["account"];
+        assert_eq!(LinkType::BelongsTo, account.link_type);
+        assert_eq!(Some("Account".to_string()), account.entity);
+    }
+
+    #[test]
+    fn metadata_unknown_field_type() {
+        let metadata: Metadata =
+            serde_json::from_str(r#"{"entityDefs":{"A":{"fields":{"x":{"type":"custom","foo":1}}}}}"#)
+                .unwrap();
+        let field = &metadata.entity("A").unwrap().fields["x"];
+
+        assert_eq!(FieldType::Other("custom".to_string()), field.field_type);
+        assert_eq!(Some(&serde_json::json!(1)), field.params.get("foo"));
+    }
+
+    #[test]
+    fn metadata_validate() {
+        let mut metadata: Metadata = serde_json::from_str(METADATA).unwrap();
+        let contact = metadata.entity_defs.get_mut("Contact").unwrap();
+        contact.fields.get_mut("firstName").unwrap().required = true;
+
+        let valid = serde_json::json!({ "firstName": "John", "status": "New" });
+        assert_eq!(Ok(()), contact.validate(&valid, ValidationMode::Create));
+
+        let errors = contact
+            .validate(&serde_json::json!({ "status": "Old" }), ValidationMode::Create)
+            .unwrap_err();
+        let kinds: Vec<_> = errors.into_iter().map(|x| x.kind).collect();
+        assert_eq!(
+            vec![FieldErrorKind::Required, FieldErrorKind::InvalidOption("Old".to_string())],
+            kinds
+        );
+
+        let too_long = serde_json::json!({ "firstName": "a".repeat(101) });
+        let errors = contact.validate(&too_long, ValidationMode::Update).unwrap_err();
+        assert_eq!(FieldErrorKind::TooLong { max_length: 100 }, errors[0].kind);
+
+        assert_eq!(Ok(()), contact.validate(&serde_json::json!({}), ValidationMode::Update));
+    }
+
+    #[test]
+    fn metadata_validate_links() {
+        let mut metadata: Metadata = serde_json::from_str(METADATA).unwrap();
+        let contact = metadata.entity_defs.get_mut("Contact").unwrap();
+        contact.fields.get_mut("account").unwrap().required = true;
+        contact.fields.get_mut("teams").unwrap().required = true;
+
+        let valid = serde_json::json!({ "accountId": "a1", "teamsIds": ["t1", "t2"] });
+        assert_eq!(Ok(()), contact.validate(&valid, ValidationMode::Create));
+
+        let errors = contact
+            .validate(&serde_json::json!({ "account": "a1", "teamsIds": null }), ValidationMode::Create)
+            .unwrap_err();
+        let fields: Vec<_> = errors.iter().map(|x| (x.field.as_str(), x.kind.clone())).collect();
+        assert_eq!(vec![("account", FieldErrorKind::Required), ("teams", FieldErrorKind::Required)], fields);
+
+        let errors = contact
+            .validate(&serde_json::json!({ "accountId": 1, "teamsIds": "t1" }), ValidationMode::Update)
+            .unwrap_err();
+        let kinds: Vec<_> = errors.into_iter().map(|x| x.kind).collect();
+        assert_eq!(
+            vec![FieldErrorKind::InvalidType(FieldType::Link), FieldErrorKind::InvalidType(FieldType::LinkMultiple)],
+            kinds
+        );
+    }
 }
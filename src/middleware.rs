@@ -0,0 +1,186 @@
+use crate::espocrm_error::EspoError;
+use crate::{debug_if, trace_if};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use std::fmt::Debug;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Hooks which run around every request the client sends, registered with [add_middleware](crate::EspoApiClient::add_middleware).
+///
+/// Authentication ([Auth](crate::Auth)), retries ([RetryPolicy](crate::RetryPolicy)) and logging are middleware as well.
+/// They run after the middleware added to the client, in that order.
+///
+/// Both hooks are called for every attempt of a request, so a request which is retried passes through them more than once.
+/// Logging in with [Auth::session](crate::Auth::session) passes through them as well, as a GET request to `App/user`.
+/// ```rust
+/// use espocrm_rs::{AfterReceive, EspoApiClient, EspoError, EspoRequest, Middleware, ResponseHead};
+///
+/// /// Adds a correlation id, and logs the latency of every mutating request
+/// #[derive(Debug)]
+/// struct Audit;
+///
+/// impl Middleware for Audit {
+///     fn before_send(&self, request: &mut EspoRequest) -> Result<(), EspoError> {
+///         request.set_header("X-Correlation-Id", "e0c4b5d2")
+///     }
+///
+///     fn after_receive(&self, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> AfterReceive {
+///         if request.method != reqwest::Method::GET {
+///             let status = response.map(|x| x.status.as_u16()).unwrap_or_default();
+///             println!("{} {}: {status} in {:?}", request.method, request.action, request.started_at.elapsed());
+///         }
+///
+///         AfterReceive::Continue
+///     }
+/// }
+///
+/// let client = EspoApiClient::new("https://espocrm.example.com")
+///     .add_middleware(Audit)
+///     .build();
+/// ```
+pub trait Middleware: Debug + Send + Sync {
+    /// Called before a request is sent.
+    ///
+    /// # Errors
+    ///
+    /// The request is not sent if an error is returned, and the error is returned to the caller
+    fn before_send(&self, request: &mut EspoRequest) -> Result<(), EspoError> {
+        let _ = request;
+        Ok(())
+    }
+
+    /// Called after a response was received, or sending the request failed.
+    /// The response body is not available, as it has not been read yet.
+    ///
+    /// All middleware is called. If any of them returns [AfterReceive::Retry], the request is sent again after the delay of the first one
+    fn after_receive(&self, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> AfterReceive {
+        let _ = (request, response);
+        AfterReceive::Continue
+    }
+}
+
+/// What to do with a response, returned by [Middleware::after_receive]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AfterReceive {
+    /// Return the response to the caller
+    Continue,
+    /// Send the request again after waiting for the duration
+    Retry(Duration),
+}
+
+/// A request to EspoCRM, as seen by [Middleware]
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct EspoRequest {
+    pub method: reqwest::Method,
+    /// The URL of the request, including the query string
+    pub url: String,
+    /// The part of the URL after `/api/v1/`, without the query string, e.g. `Contact/1`
+    pub action: String,
+    pub headers: HeaderMap,
+    /// The JSON body of the request
+    pub body: Option<Vec<u8>>,
+    /// The timeout of the request, if any
+    pub timeout: Option<Duration>,
+    /// The number of the attempt, starting at 1
+    pub attempt: u32,
+    /// When this attempt started, before any middleware was called
+    pub started_at: Instant,
+    /// Whether the session token was refreshed after EspoCRM rejected it. Shared by all attempts of the request
+    pub(crate) token_refreshed: Arc<AtomicBool>,
+    /// Whether this logs in with [Auth::session](crate::Auth::session), authenticated with the password instead of the token
+    pub(crate) login: bool,
+}
+
+impl EspoRequest {
+    pub(crate) fn new(method: reqwest::Method, url: String, action: &str) -> Self {
+        Self {
+            method,
+            url,
+            action: action.to_string(),
+            headers: HeaderMap::new(),
+            body: None,
+            timeout: None,
+            attempt: 1,
+            started_at: Instant::now(),
+            token_refreshed: Arc::default(),
+            login: false,
+        }
+    }
+
+    /// Set a header, replacing any previous value
+    ///
+    /// # Errors
+    ///
+    /// If the name or value is not a valid header name or value, as [EspoError::Middleware]
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), EspoError> {
+        self.insert_header(name, value, false)
+    }
+
+    /// Like [Self::set_header], for headers containing a credential, which are left out of reqwest's `Debug` output
+    pub fn set_sensitive_header(&mut self, name: &str, value: &str) -> Result<(), EspoError> {
+        self.insert_header(name, value, true)
+    }
+
+    fn insert_header(&mut self, name: &str, value: &str, sensitive: bool) -> Result<(), EspoError> {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|x| EspoError::Middleware(Box::new(x)))?;
+        let mut value = HeaderValue::from_str(value).map_err(|x| EspoError::Middleware(Box::new(x)))?;
+        value.set_sensitive(sensitive);
+
+        self.headers.insert(name, value);
+        Ok(())
+    }
+}
+
+/// The status and headers of a response from EspoCRM, as seen by [Middleware]
+#[derive(Clone, Copy, Debug)]
+pub struct ResponseHead<'a> {
+    pub status: StatusCode,
+    pub headers: &'a HeaderMap,
+}
+
+/// Logs requests and responses when the `tracing` feature is enabled
+#[derive(Debug)]
+pub(crate) struct Logging;
+
+impl Middleware for Logging {
+    #[allow(unused)]
+    fn before_send(&self, request: &mut EspoRequest) -> Result<(), EspoError> {
+        trace_if!("Sending request to EspoCRM");
+        Ok(())
+    }
+
+    #[allow(unused)]
+    fn after_receive(&self, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> AfterReceive {
+        match response {
+            Ok(response) => debug_if!("Got response from EspoCRM with status code: {}", response.status),
+            Err(error) => debug_if!("Got an error from EspoCRM: {error}"),
+        }
+
+        AfterReceive::Continue
+    }
+}
+
+/// Run the `before_send` hook of all middleware, in order
+pub(crate) fn before_send<'a, I>(middleware: I, request: &mut EspoRequest) -> Result<(), EspoError>
+where
+    I: IntoIterator<Item = &'a dyn Middleware>,
+{
+    middleware.into_iter().try_for_each(|x| x.before_send(request))
+}
+
+/// Run the `after_receive` hook of all middleware, in order, and return the delay of the first one asking to retry
+pub(crate) fn after_receive<'a, I>(middleware: I, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> Option<Duration>
+where
+    I: IntoIterator<Item = &'a dyn Middleware>,
+{
+    middleware
+        .into_iter()
+        .map(|x| x.after_receive(request, response))
+        .fold(None, |retry, action| match (retry, action) {
+            (None, AfterReceive::Retry(delay)) => Some(delay),
+            (retry, _) => retry,
+        })
+}
@@ -0,0 +1,91 @@
+use crate::espocrm_error::EspoError;
+use crate::espocrm_types::{FilterType, Order, PaginationOptions, Params, StableOrder, Value, Where, MAX_PAGE_SIZE};
+use serde::de::Error;
+
+/// Keeps track of where [list_stream](crate::EspoApiClient::list_stream) is in the result set
+#[derive(Clone, Debug)]
+pub(crate) struct PageState {
+    params: Params,
+    options: PaginationOptions,
+    offset: i64,
+    last_id: Option<String>,
+    done: bool,
+}
+
+impl PageState {
+    pub(crate) fn new(params: Params, mut options: PaginationOptions) -> Self {
+        // `page_size` is public, so it may not have gone through the setter
+        options.page_size = options.page_size.clamp(1, MAX_PAGE_SIZE);
+
+        Self {
+            offset: params.offset.unwrap_or(0),
+            params,
+            options,
+            last_id: None,
+            done: false,
+        }
+    }
+
+    /// The parameters to fetch the next page with, or `None` if all pages have been fetched
+    pub(crate) fn next_params(&self) -> Option<Params> {
+        if self.done {
+            return None;
+        }
+
+        let mut params = self.params.clone();
+        params.set_max_size(self.options.page_size);
+
+        match self.options.stable_order {
+            StableOrder::None => {
+                params.set_offset(self.offset);
+            }
+            StableOrder::CreatedAt => {
+                params.set_offset(self.offset);
+                params.set_order_by("createdAt");
+                params.set_order(Order::Asc);
+            }
+            StableOrder::Id => {
+                params.set_order_by("id");
+                params.set_order(Order::Asc);
+
+                // The first page starts at the offset in the params, the following pages after the last id
+                if let Some(last_id) = &self.last_id {
+                    params.offset = None;
+                    let mut r#where = params.r#where.take().unwrap_or_default();
+                    r#where.push(Where::new(FilterType::GreaterThan, "id", Some(Value::str(last_id))));
+                    params.set_where(r#where);
+                }
+            }
+        }
+
+        Some(params)
+    }
+
+    /// Record that a page was fetched. `total` is the total reported by EspoCRM for that page's query
+    pub(crate) fn advance(&mut self, total: i64, page: &[serde_json::Value]) -> Result<(), EspoError> {
+        let len = page.len() as i64;
+
+        // With keyset pagination the total only counts the records after the last id,
+        // with offsets it counts all records including those already fetched
+        let remaining = match self.options.stable_order {
+            StableOrder::Id if self.last_id.is_some() => total,
+            StableOrder::Id | StableOrder::None | StableOrder::CreatedAt => total - self.offset,
+        };
+
+        if self.options.stable_order == StableOrder::Id {
+            if let Some(last) = page.last() {
+                let id = last
+                    .get("id")
+                    .and_then(|x| x.as_str())
+                    .ok_or_else(|| serde_json::Error::custom("Record has no `id` attribute, which is required for StableOrder::Id"))?;
+                self.last_id = Some(id.to_string());
+            }
+        }
+
+        self.offset += len;
+        // A negative total means EspoCRM did not count the records
+        self.done = len < self.options.page_size || (total >= 0 && len >= remaining);
+
+        Ok(())
+    }
+}
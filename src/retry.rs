@@ -0,0 +1,157 @@
+use crate::debug_if;
+use crate::espocrm_api_client::Method;
+use crate::espocrm_error::EspoError;
+use crate::middleware::{AfterReceive, EspoRequest, Middleware, ResponseHead};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// Policy for retrying requests which failed because of a transient error, such as a `503` from a load balancer or a reset connection.
+/// Set it on the client with [set_retry_policy](crate::EspoApiClient::set_retry_policy).
+///
+/// Between attempts the client waits with exponential backoff: the first retry waits `initial_backoff`, and every following retry waits twice as long, up to `max_backoff`.
+/// If EspoCRM sends a `Retry-After` header with a number of seconds, that is used instead, also up to `max_backoff`.
+///
+/// By default only the idempotent methods GET, PUT and DELETE are retried, so a record is never created twice.
+///
+/// The client retries requests through the policy's implementation of [Middleware].
+/// ```rust
+/// use espocrm_rs::{EspoApiClient, Method, RetryPolicy};
+/// use std::time::Duration;
+///
+/// let client = EspoApiClient::new("https://espocrm.example.com")
+///     .set_retry_policy(RetryPolicy::new()
+///         .set_max_attempts(5)
+///         .set_initial_backoff(Duration::from_millis(500))
+///         .build())
+///     .build();
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The total number of attempts, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Randomize the backoff between half and the full delay, so clients that failed at the same time don't retry at the same time
+    pub jitter: bool,
+    /// The status codes which are retried
+    pub statuses: Vec<StatusCode>,
+    /// The methods which are retried
+    pub methods: Vec<Method>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy which makes at most 3 attempts, waiting 250ms before the first retry and at most 10 seconds between attempts.
+    /// It retries `429`, `502`, `503` and `504` responses and connection errors for GET, PUT and DELETE requests.
+    pub fn new() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            methods: vec![Method::Get, Method::Put, Method::Delete],
+        }
+    }
+
+    /// Set the total number of attempts, including the first one. A value of 1 disables retrying
+    pub fn set_max_attempts(&mut self, max_attempts: u32) -> &mut Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn set_initial_backoff(&mut self, initial_backoff: Duration) -> &mut Self {
+        self.initial_backoff = initial_backoff;
+        self
+    }
+
+    pub fn set_max_backoff(&mut self, max_backoff: Duration) -> &mut Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn set_jitter(&mut self, jitter: bool) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn set_statuses<I: IntoIterator<Item = StatusCode>>(&mut self, statuses: I) -> &mut Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Set the methods which are retried.
+    /// Only add POST or PATCH if retrying them can't cause problems, as a request may have been processed by EspoCRM before the connection failed
+    pub fn set_methods<I: IntoIterator<Item = Method>>(&mut self, methods: I) -> &mut Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    pub fn build(&self) -> Self {
+        self.clone()
+    }
+
+    pub(crate) fn retries_method(&self, method: &reqwest::Method) -> bool {
+        self.methods.iter().any(|x| reqwest::Method::from(x.clone()) == method)
+    }
+
+    pub(crate) fn retries_status(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    /// Whether a request which failed with `error` may succeed when sent again
+    pub(crate) fn retries_error(&self, error: &reqwest::Error) -> bool {
+        error.is_connect() || error.is_timeout() || error.is_request()
+    }
+
+    /// The time to wait before the next attempt, after `attempt` attempts have failed
+    pub(crate) fn backoff(&self, attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+        if let Some(retry_after) = headers.and_then(retry_after) {
+            return retry_after.min(self.max_backoff);
+        }
+
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self.initial_backoff.saturating_mul(factor).min(self.max_backoff);
+
+        if self.jitter {
+            let half = backoff / 2;
+            half + half.mul_f64(fastrand::f64())
+        } else {
+            backoff
+        }
+    }
+}
+
+impl Middleware for RetryPolicy {
+    fn after_receive(&self, request: &EspoRequest, response: Result<ResponseHead, &EspoError>) -> AfterReceive {
+        if !self.retries_method(&request.method) || request.attempt >= self.max_attempts {
+            return AfterReceive::Continue;
+        }
+
+        let backoff = match response {
+            Ok(response) if self.retries_status(response.status) => self.backoff(request.attempt, Some(response.headers)),
+            Err(EspoError::Transport(error) | EspoError::Timeout(error)) if self.retries_error(error) => self.backoff(request.attempt, None),
+            _ => return AfterReceive::Continue,
+        };
+
+        debug_if!("Retrying request to EspoCRM in {backoff:?}, attempt {} of {}", request.attempt + 1, self.max_attempts);
+        AfterReceive::Retry(backoff)
+    }
+}
+
+/// Parse a `Retry-After` header containing a number of seconds. HTTP dates are not supported
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
+}
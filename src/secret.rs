@@ -0,0 +1,24 @@
+use std::fmt;
+use zeroize::Zeroizing;
+
+/// A credential, such as a password or API key.
+/// Its memory is zeroed when it is dropped, and it is never included in `Debug` output.
+#[derive(Clone, PartialEq, Eq)]
+pub(crate) struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub(crate) fn new(value: &str) -> Self {
+        Self(Zeroizing::new(value.to_string()))
+    }
+
+    /// The secret value. Take care not to log or copy it
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
@@ -1,172 +1,123 @@
-use crate::espocrm_types::{Params, Value};
+use crate::espocrm_error::SerializeError;
+use crate::espocrm_types::{FilterType, Params, Value, Where};
 use urlencoding::encode;
 
-pub fn serialize(input: Params) -> Result<String, &'static str> {
-    let mut builder = String::new();
-
-    if input.select.is_some() {
-        if builder.len() > 0 {
-            builder.push('&');
-        }
-
-        builder.push_str(&format!("select={}", input.select.unwrap()))
+/// Lowercase the first character of a PascalCase string, turning it into camelCase
+fn lower_camel_case(input: &str) -> String {
+    let mut chars = input.chars();
+    match chars.next() {
+        Some(first) => first.to_lowercase().chain(chars).collect(),
+        None => String::new(),
     }
+}
 
-    if input.order_by.is_some() {
-        if builder.len() > 0 {
-            builder.push('&');
-        }
+/// Serialize the parameters of a GET request the way PHP's `http_build_query` does.
+///
+/// All keys and values are percent-encoded. Like `http_build_query`, values which are `None`, e.g. `Value::String(None)`, are left out.
+/// An explicit [Value::Null] is sent as an empty value, `key=`, so it can be told apart from a missing value.
+pub fn serialize(input: Params) -> Result<String, SerializeError> {
+    let mut parts = Vec::new();
 
-        builder.push_str(&format!("orderBy={}", input.order_by.unwrap().to_string()))
+    if let Some(select) = input.select {
+        push(&mut parts, "select", &select);
     }
 
-    if input.order.is_some() {
-        if builder.len() > 0 {
-            builder.push('&');
-        }
-
-        let lower_camel_case_type = {
-            let mut builder = String::new();
-            let mut is_first = true;
-            for c in input.order.unwrap().to_string().chars() {
-                if is_first {
-                    let mut x = String::new();
-                    x.push(c);
-                    let x_upper = x.to_lowercase();
-
-                    builder.push_str(&x_upper);
-                    is_first = false;
-                } else {
-                    builder.push(c)
-                }
-            }
+    if let Some(order_by) = input.order_by {
+        push(&mut parts, "orderBy", &order_by);
+    }
 
-            builder
-        };
+    if let Some(order) = input.order {
+        push(&mut parts, "order", &lower_camel_case(&order.to_string()));
+    }
 
-        builder.push_str(&format!("order={}", lower_camel_case_type))
+    if let Some(offset) = input.offset {
+        push(&mut parts, "offset", &offset.to_string());
     }
 
-    if input.offset.is_some() {
-        if builder.len() > 0 {
-            builder.push('&');
+    if let Some(bool_filter_list) = input.bool_filter_list {
+        for bool_filter in bool_filter_list {
+            push(&mut parts, "boolFilterList[]", &bool_filter.to_string());
         }
+    }
 
-        builder.push_str(&format!("offset={}", input.offset.unwrap().to_string()))
+    if let Some(max_size) = input.max_size {
+        push(&mut parts, "maxSize", &max_size.to_string());
     }
 
-    if input.bool_filter_list.is_some() {
-        todo!();
+    if let Some(primary_filter) = input.primary_filter {
+        push(&mut parts, "primaryFilter", &primary_filter);
     }
 
-    if input.max_size.is_some() {
-        if builder.len() > 0 {
-            builder.push('&');
+    if let Some(r#where) = input.r#where {
+        for (i, v) in r#where.into_iter().enumerate() {
+            serialize_where(&format!("where[{}]", i), v, &mut parts)?;
         }
-
-        builder.push_str(&format!("maxSize={}", input.max_size.unwrap().to_string()));
     }
 
-    if input.primary_filter.is_some() {
-        if builder.len() > 0 {
-            builder.push('&');
-        }
+    Ok(parts.join("&"))
+}
 
-        builder.push_str(&format!("primaryFilter={}", input.primary_filter.unwrap()));
-    }
+/// Add a percent-encoded `key=value` pair
+fn push(parts: &mut Vec<String>, key: &str, value: &str) {
+    parts.push(format!("{}={}", encode(key), encode(value)));
+}
 
-    if input.r#where.is_some() {
-        if builder.len() > 0 {
-            builder.push('&');
-        }
+/// Serialize a where clause, and any clauses nested in it.
+/// `prefix` is the key of the clause, e.g. `where[0]` or `where[0][value][1]`
+fn serialize_where(prefix: &str, clause: Where, parts: &mut Vec<String>) -> Result<(), SerializeError> {
+    match clause {
+        Where::Clause { r#type, attribute, value } => {
+            if r#type == FilterType::Or {
+                return Err(SerializeError::InvalidGroup(r#type));
+            }
+            if attribute.is_empty() {
+                return Err(SerializeError::MissingAttribute(r#type));
+            }
+
+            push(parts, &format!("{}[type]", prefix), &lower_camel_case(&r#type.to_string()));
+            push(parts, &format!("{}[attribute]", prefix), &attribute);
 
-        let mut i = 0;
-        for v in input.r#where.unwrap() {
-            if i > 0 {
-                builder.push('&');
+            if let Some(value) = value {
+                serialize_value(&format!("{}[value]", prefix), value, parts)?;
             }
+        }
+        // Groups have no attribute, their value holds the nested clauses
+        Where::Group { r#type, clauses } => {
+            push(parts, &format!("{}[type]", prefix), &lower_camel_case(&r#type.to_string()));
 
-            let lower_camel_case_type = {
-                let mut builder = String::new();
-                let mut is_first = true;
-                for c in v.r#type.to_string().chars() {
-                    if is_first {
-                        let mut x = String::new();
-                        x.push(c);
-                        let x_upper = x.to_lowercase();
-
-                        builder.push_str(&x_upper);
-                        is_first = false;
-                    } else {
-                        builder.push(c)
-                    }
-                }
-
-                builder
-            };
-
-            builder.push_str(&format!(
-                "{}={}",
-                &encode(&format!("where[{}][type]", i)),
-                lower_camel_case_type
-            ));
-            builder.push('&');
-            builder.push_str(&format!(
-                "{}={}",
-                &encode(&format!("where[{}][attribute]", i)),
-                v.attribute
-            ));
-
-            if v.value.is_some() {
-                let val_unwrapped = v.value.unwrap();
-                match val_unwrapped {
-                    Value::Array(arr) => {
-                        let arr_1 = arr.unwrap();
-
-                        let mut j = 0;
-                        for elem in arr_1 {
-                            let elem_v_inner = match elem {
-                                Value::String(inner) => inner.unwrap(),
-                                Value::Integer(inner) => inner.unwrap().to_string(),
-                                Value::Boolean(inner) => inner.unwrap().to_string(),
-                                Value::Array(_) => {
-                                    unimplemented!();
-                                }
-                            };
-
-                            builder.push('&');
-                            builder.push_str(&format!(
-                                "{}={}",
-                                &encode(&format!("where[{}][value][{}]", i, j)),
-                                elem_v_inner
-                            ));
-
-                            j += 1;
-                        }
-                    }
-                    _ => {
-                        let elem_v_inner = match val_unwrapped {
-                            Value::String(inner) => inner.unwrap(),
-                            Value::Integer(inner) => inner.unwrap().to_string(),
-                            Value::Boolean(inner) => inner.unwrap().to_string(),
-                            _ => {
-                                panic!("Unreachable")
-                            }
-                        };
-
-                        builder.push('&');
-                        builder.push_str(&format!(
-                            "{}={}",
-                            &encode(&format!("where[{}][value]", i)),
-                            elem_v_inner
-                        ));
-                    }
-                }
+            for (j, clause) in clauses.into_iter().enumerate() {
+                serialize_where(&format!("{}[value][{}]", prefix, j), clause, parts)?;
             }
+        }
+    }
 
-            i += 1;
+    Ok(())
+}
+
+fn serialize_value(key: &str, value: Value, parts: &mut Vec<String>) -> Result<(), SerializeError> {
+    match value {
+        Value::String(Some(inner)) => push(parts, key, &inner),
+        Value::Integer(Some(inner)) => push(parts, key, &inner.to_string()),
+        Value::Boolean(Some(inner)) => push(parts, key, &inner.to_string()),
+        Value::Float(Some(inner)) if inner.is_finite() => push(parts, key, &inner.to_string()),
+        Value::Float(Some(_)) => return Err(SerializeError::NonFiniteFloat(key.to_string())),
+        Value::Date(Some(inner)) => push(parts, key, &inner.to_string()),
+        Value::DateTime(Some(inner)) => push(parts, key, &inner.to_string()),
+        Value::Null => push(parts, key, ""),
+        Value::Array(Some(inner)) => {
+            for (j, elem) in inner.into_iter().enumerate() {
+                serialize_value(&format!("{}[{}]", key, j), elem, parts)?;
+            }
         }
+        // Missing values are left out, just like http_build_query does
+        Value::String(None)
+        | Value::Integer(None)
+        | Value::Boolean(None)
+        | Value::Float(None)
+        | Value::Date(None)
+        | Value::DateTime(None)
+        | Value::Array(None) => {}
     }
 
-    Ok(builder)
+    Ok(())
 }
@@ -0,0 +1,234 @@
+use crate::debug_if;
+use crate::espocrm_error::{EspoError, STATUS_REASON_HEADER};
+use crate::middleware::EspoRequest;
+use crate::secret::Secret;
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use zeroize::Zeroizing;
+
+/// The action EspoCRM logs in with
+pub(crate) const LOGIN_ACTION: &str = "App/user";
+
+const AUTHORIZATION_HEADER: &str = "Espo-Authorization";
+
+/// The `X-Status-Reason` EspoCRM responds with when a login needs a two-factor code
+const SECOND_STEP_REQUIRED: &str = "second-step-required";
+
+type CodeCallback = dyn Fn() -> Result<String, Box<dyn Error + Send + Sync>> + Send + Sync;
+
+/// The code for the second step of logging in to a user with two-factor authentication, such as a TOTP code.
+/// Used with [Auth::session_2fa](crate::Auth::session_2fa)
+/// ```rust
+/// use espocrm_rs::{Auth, EspoApiClient, TwoFactorCode};
+///
+/// let client = EspoApiClient::new("https://espocrm.example.com")
+///     .set_auth(Auth::session_2fa(
+///         "Your Username here",
+///         "Your Password here",
+///         TwoFactorCode::callback(|| {
+///             let mut code = String::new();
+///             std::io::stdin().read_line(&mut code)?;
+///             Ok(code.trim().to_string())
+///         }),
+///     ))
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct TwoFactorCode(TwoFactorKind);
+
+#[derive(Clone)]
+enum TwoFactorKind {
+    Fixed(Secret),
+    Callback(Arc<CodeCallback>),
+}
+
+impl TwoFactorCode {
+    /// Use a code which was already obtained.
+    /// A TOTP code expires after a short while, so logging in again once the token has expired will fail.
+    /// Use [Self::callback] for long running programs
+    pub fn fixed<S: AsRef<str>>(code: S) -> Self {
+        Self(TwoFactorKind::Fixed(Secret::new(code.as_ref())))
+    }
+
+    /// Call `callback` to obtain a code every time EspoCRM asks for one, e.g. by prompting the user or generating a TOTP code.
+    /// The request waits until the callback returns. If it fails, the error is returned as [EspoError::Auth].
+    ///
+    /// [EspoApiClient](crate::EspoApiClient) runs the callback on tokio's blocking thread pool, so it may block, e.g. to read from stdin
+    pub fn callback<F>(callback: F) -> Self
+    where
+        F: Fn() -> Result<String, Box<dyn Error + Send + Sync>> + Send + Sync + 'static,
+    {
+        Self(TwoFactorKind::Callback(Arc::new(callback)))
+    }
+
+    #[cfg(feature = "blocking")]
+    pub(crate) fn code(&self) -> Result<Secret, EspoError> {
+        match &self.0 {
+            TwoFactorKind::Fixed(code) => Ok(code.clone()),
+            TwoFactorKind::Callback(callback) => {
+                let code = Zeroizing::new(callback().map_err(EspoError::Auth)?);
+                Ok(Secret::new(&code))
+            }
+        }
+    }
+
+    /// Like [Self::code], without blocking the async runtime while the callback runs
+    pub(crate) async fn code_async(&self) -> Result<Secret, EspoError> {
+        match &self.0 {
+            TwoFactorKind::Fixed(code) => Ok(code.clone()),
+            TwoFactorKind::Callback(callback) => {
+                let callback = callback.clone();
+                let code = match tokio::task::spawn_blocking(move || callback().map(Zeroizing::new)).await {
+                    Ok(code) => code.map_err(EspoError::Auth)?,
+                    // Let a panic in the callback propagate, as if it had been called directly
+                    Err(error) => match error.try_into_panic() {
+                        Ok(panic) => std::panic::resume_unwind(panic),
+                        Err(error) => return Err(EspoError::Auth(Box::new(error))),
+                    },
+                };
+
+                Ok(Secret::new(&code))
+            }
+        }
+    }
+}
+
+impl fmt::Debug for TwoFactorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.0 {
+            TwoFactorKind::Fixed(code) => f.debug_tuple("Fixed").field(code).finish(),
+            TwoFactorKind::Callback(_) => f.write_str("Callback"),
+        }
+    }
+}
+
+/// The user logged in with [Auth::session](crate::Auth::session), as returned by `GET /api/v1/App/user`
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionUser {
+    pub id: String,
+    pub user_name: String,
+    pub name: Option<String>,
+    /// The type of the user, e.g. `regular` or `admin`
+    #[serde(rename = "type")]
+    pub user_type: Option<String>,
+    /// Any other attributes of the user
+    #[serde(flatten)]
+    pub attributes: BTreeMap<String, serde_json::Value>,
+}
+
+/// Username and password authentication which logs in once, and then authenticates with the token issued by EspoCRM.
+/// Shared by all clones of a client
+#[derive(Debug)]
+pub(crate) struct Session {
+    username: String,
+    password: Secret,
+    second_factor: Option<TwoFactorCode>,
+    state: Mutex<Option<SessionState>>,
+    /// Held while logging in, so concurrent requests don't all log in
+    pub(crate) login_lock: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug)]
+struct SessionState {
+    token: Secret,
+    user: SessionUser,
+}
+
+#[derive(Deserialize)]
+struct LoginResponse {
+    token: String,
+    user: SessionUser,
+}
+
+impl Session {
+    pub(crate) fn new(username: &str, password: &str, second_factor: Option<TwoFactorCode>) -> Self {
+        Self {
+            username: username.to_string(),
+            password: Secret::new(password),
+            second_factor,
+            state: Mutex::new(None),
+            login_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, Option<SessionState>> {
+        self.state.lock().unwrap_or_else(|x| x.into_inner())
+    }
+
+    /// The cached token, if logged in
+    pub(crate) fn token(&self) -> Option<Secret> {
+        self.state().as_ref().map(|x| x.token.clone())
+    }
+
+    pub(crate) fn user(&self) -> Option<SessionUser> {
+        self.state().as_ref().map(|x| x.user.clone())
+    }
+
+    /// Forget the token `request` was sent with after EspoCRM rejected it, unless another request already replaced it
+    pub(crate) fn invalidate(&self, request: &EspoRequest) {
+        let mut state = self.state();
+        let sent = request.headers.get(AUTHORIZATION_HEADER);
+        if state.as_ref().is_some_and(|x| sent.is_some_and(|sent| sent == self.authorization(&x.token).as_str())) {
+            *state = None;
+        }
+    }
+
+    /// The value of the `Espo-Authorization` header for `secret`, which is either the password or a token
+    fn authorization(&self, secret: &Secret) -> Zeroizing<String> {
+        let credentials = Zeroizing::new(format!("{}:{}", self.username, secret.expose()));
+        Zeroizing::new(base64::encode(credentials.as_bytes()))
+    }
+
+    fn authorize(&self, request: &mut EspoRequest, secret: &Secret, by_token: bool) -> Result<(), EspoError> {
+        request.set_sensitive_header(AUTHORIZATION_HEADER, &self.authorization(secret))?;
+        request.set_header("Espo-Authorization-By-Token", if by_token { "true" } else { "false" })
+    }
+
+    /// Authenticate a request with the cached token. Does nothing if not logged in
+    pub(crate) fn authorize_with_token(&self, request: &mut EspoRequest) -> Result<(), EspoError> {
+        match self.token() {
+            Some(token) => self.authorize(request, &token, true),
+            None => Ok(()),
+        }
+    }
+
+    /// Authenticate the login request with the password, and the two-factor code if EspoCRM asked for one
+    pub(crate) fn authorize_login(&self, request: &mut EspoRequest, code: Option<&Secret>) -> Result<(), EspoError> {
+        self.authorize(request, &self.password, false)?;
+        match code {
+            Some(code) => request.set_sensitive_header("Espo-Authorization-Code", code.expose()),
+            None => Ok(()),
+        }
+    }
+
+    /// How to obtain the two-factor code to log in again with, if the response to a login asks for one and a [TwoFactorCode] was set.
+    /// Without a [TwoFactorCode], the response is reported as [EspoError::Unauthorized]
+    pub(crate) fn second_step(&self, status: StatusCode, headers: &HeaderMap) -> Option<&TwoFactorCode> {
+        let required = status == StatusCode::UNAUTHORIZED
+            && headers.get(STATUS_REASON_HEADER).is_some_and(|x| x == SECOND_STEP_REQUIRED);
+        if !required {
+            return None;
+        }
+
+        debug_if!("EspoCRM requires a two-factor code to log in");
+        self.second_factor.as_ref()
+    }
+
+    /// Cache the token and user from the body of a successful login response
+    pub(crate) fn store(&self, body: &[u8]) -> Result<SessionUser, EspoError> {
+        let response: LoginResponse = serde_json::from_slice(body)?;
+        let token = Secret::new(&response.token);
+        let user = response.user;
+        // Don't leave a copy of the token behind
+        drop(Zeroizing::new(response.token));
+
+        *self.state() = Some(SessionState { token, user: user.clone() });
+        Ok(user)
+    }
+}
@@ -0,0 +1,144 @@
+use crate::espocrm_error::EspoError;
+use crate::middleware::EspoRequest;
+use futures::future::BoxFuture;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::StatusCode;
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+/// Sends requests to EspoCRM. [reqwest::Client] is the default transport, and can be replaced with [set_transport](crate::EspoApiClient::set_transport),
+/// e.g. with a [MemoryTransport] in tests.
+///
+/// The transport receives requests after they passed through the [Middleware](crate::Middleware), so they are authenticated.
+/// It is called once for every attempt of a request.
+pub trait Transport: Debug + Send + Sync {
+    /// Send a request and return the response, without checking its status code.
+    /// The timeout of the request is in [EspoRequest::timeout], if one is set
+    ///
+    /// # Errors
+    ///
+    /// If the request could not be sent, or no response was received
+    fn send<'a>(&'a self, request: &'a EspoRequest) -> BoxFuture<'a, Result<reqwest::Response, EspoError>>;
+}
+
+impl Transport for reqwest::Client {
+    fn send<'a>(&'a self, request: &'a EspoRequest) -> BoxFuture<'a, Result<reqwest::Response, EspoError>> {
+        let mut request_builder = self
+            .request(request.method.clone(), &request.url)
+            .headers(request.headers.clone());
+
+        if let Some(body) = &request.body {
+            request_builder = request_builder.body(body.clone());
+        }
+
+        if let Some(timeout) = request.timeout {
+            request_builder = request_builder.timeout(timeout);
+        }
+
+        Box::pin(async move { Ok(request_builder.send().await?) })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send<'a>(&'a self, request: &'a EspoRequest) -> BoxFuture<'a, Result<reqwest::Response, EspoError>> {
+        self.as_ref().send(request)
+    }
+}
+
+/// A [Transport] which records requests and returns canned responses, without any network access.
+/// Responses and errors are returned in the order they were added. Once none are left, requests fail with [EspoError::CustomTransport].
+/// ```rust
+/// use espocrm_rs::{Auth, EspoApiClient, MemoryTransport};
+/// use reqwest::StatusCode;
+/// use std::sync::Arc;
+///
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// let transport = Arc::new(MemoryTransport::new());
+/// transport.push_response(StatusCode::OK, r#"{"id":"1","name":"Alice"}"#);
+///
+/// let client = EspoApiClient::new("https://espocrm.example.com")
+///     .set_auth(Auth::api_key("key"))
+///     .set_transport(transport.clone())
+///     .build();
+///
+/// let contact: serde_json::Value = client.read("Contact", "1").await.unwrap().json().await.unwrap();
+/// assert_eq!("Alice", contact["name"]);
+///
+/// let requests = transport.requests();
+/// assert_eq!("https://espocrm.example.com/api/v1/Contact/1", requests[0].url);
+/// assert_eq!("key", requests[0].headers["X-Api-Key"]);
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct MemoryTransport {
+    responses: Mutex<VecDeque<Result<CannedResponse, EspoError>>>,
+    requests: Mutex<Vec<EspoRequest>>,
+}
+
+/// A response added to a [MemoryTransport]
+type CannedResponse = http::Response<Vec<u8>>;
+
+impl MemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a response with a JSON body, which is returned after the responses added before it
+    pub fn push_response<B: Into<Vec<u8>>>(&self, status: StatusCode, body: B) -> &Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        self.push_response_with_headers(status, headers, body)
+    }
+
+    /// Add a response with `headers`, e.g. `X-Status-Reason` or `Retry-After`, which is returned after the responses added before it.
+    /// Unlike [Self::push_response], no `Content-Type` is added
+    /// ```rust
+    /// use espocrm_rs::MemoryTransport;
+    /// use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    /// use reqwest::StatusCode;
+    ///
+    /// let mut headers = HeaderMap::new();
+    /// headers.insert(RETRY_AFTER, HeaderValue::from_static("2"));
+    ///
+    /// let transport = MemoryTransport::new();
+    /// transport.push_response_with_headers(StatusCode::TOO_MANY_REQUESTS, headers, "");
+    /// ```
+    pub fn push_response_with_headers<B: Into<Vec<u8>>>(&self, status: StatusCode, headers: HeaderMap, body: B) -> &Self {
+        let mut response = http::Response::new(body.into());
+        *response.status_mut() = status;
+        *response.headers_mut() = headers;
+
+        self.responses.lock().unwrap_or_else(|x| x.into_inner()).push_back(Ok(response));
+        self
+    }
+
+    /// Add an error, e.g. [EspoError::CustomTransport] to simulate a failed connection, which is returned after the responses added before it
+    pub fn push_error(&self, error: EspoError) -> &Self {
+        self.responses.lock().unwrap_or_else(|x| x.into_inner()).push_back(Err(error));
+        self
+    }
+
+    /// The requests sent so far, in order
+    pub fn requests(&self) -> Vec<EspoRequest> {
+        self.requests.lock().unwrap_or_else(|x| x.into_inner()).clone()
+    }
+}
+
+impl Transport for MemoryTransport {
+    fn send<'a>(&'a self, request: &'a EspoRequest) -> BoxFuture<'a, Result<reqwest::Response, EspoError>> {
+        self.requests.lock().unwrap_or_else(|x| x.into_inner()).push(request.clone());
+
+        let next = self.responses.lock().unwrap_or_else(|x| x.into_inner()).pop_front();
+        let result = match next {
+            Some(Ok(response)) => Ok(reqwest::Response::from(response)),
+            Some(Err(error)) => Err(error),
+            None => Err(EspoError::CustomTransport(
+                format!("MemoryTransport has no response left for {} {}", request.method, request.url).into(),
+            )),
+        };
+
+        Box::pin(async move { result })
+    }
+}
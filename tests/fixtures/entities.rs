@@ -0,0 +1,57 @@
+// This file was generated by espocrm-rs from EspoCRM's metadata. Do not edit it by hand.
+
+/// The `Account` entity
+#[derive(Clone, Debug, Default, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Account {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl ::espocrm_rs::Entity for Account {
+    const ENTITY_TYPE: &'static str = "Account";
+    const FIELDS: &'static [&'static str] = &["id", "name"];
+}
+
+/// The options of the `status` field of [Contact]
+#[derive(Clone, Debug, Eq, PartialEq, Hash, ::serde::Serialize, ::serde::Deserialize)]
+pub enum ContactStatus {
+    #[serde(rename = "")]
+    Empty,
+    #[serde(rename = "New")]
+    New,
+    #[serde(rename = "In Process")]
+    InProcess,
+    /// A value not present in the metadata this code was generated from, kept as-is so it is sent back unchanged
+    #[serde(untagged)]
+    Unknown(String),
+}
+
+/// The `Contact` entity
+#[derive(Clone, Debug, Default, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Contact {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub account_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<ContactStatus>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teams_ids: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub teams_names: Option<::std::collections::HashMap<String, String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub r#type: Option<String>,
+}
+
+impl ::espocrm_rs::Entity for Contact {
+    const ENTITY_TYPE: &'static str = "Contact";
+    const FIELDS: &'static [&'static str] = &["id", "accountId", "accountName", "firstName", "status", "teamsIds", "teamsNames", "type"];
+}